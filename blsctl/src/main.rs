@@ -11,7 +11,9 @@ use std::{
     str::FromStr,
 };
 
-use blsforme::{os_release::OsRelease, BootJSON, Configuration, Entry, Manager, Root, Schema};
+use blsforme::{
+    bootloader, os_release::OsRelease, BootJSON, Configuration, Entry, Kernel, Manager, Root, Schema, Verification,
+};
 use clap::{Parser, Subcommand};
 use color_eyre::{
     eyre::{eyre, Ok},
@@ -55,7 +57,23 @@ enum Commands {
     MountBoot,
 
     /// Configure the `$BOOT` directories for next boot
-    Update,
+    Update {
+        /// Fully compare files on `$BOOT` rather than trusting recorded hashes
+        #[arg(long)]
+        verify: bool,
+
+        /// Read back files written to `$BOOT` to ensure they landed intact
+        #[arg(long)]
+        verify_writes: bool,
+
+        /// Enable hibernation by adding `resume=` for the active swap
+        #[arg(long)]
+        resume: bool,
+
+        /// Omit `root=` when the root partition is discoverable per the DPS
+        #[arg(long)]
+        dps: bool,
+    },
 
     /// Set the bootloader timeout value
     SetTimeout { timeout: u64 },
@@ -71,6 +89,12 @@ enum Commands {
 
     /// Status information (debugging)
     Status,
+
+    /// Take ownership of `$BOOT` files, allowing their removal once stale
+    Adopt { paths: Vec<PathBuf> },
+
+    /// Release ownership of `$BOOT` files, ensuring they're never removed
+    Forget { paths: Vec<PathBuf> },
 }
 
 fn scan_os_release(root: impl AsRef<Path>) -> color_eyre::Result<OsRelease> {
@@ -95,7 +119,7 @@ fn scan_os_release(root: impl AsRef<Path>) -> color_eyre::Result<OsRelease> {
 }
 
 /// Query the schema we need to use for pre BLS schema installations
-fn query_schema(os_release: &OsRelease) -> color_eyre::Result<Schema> {
+fn query_schema(os_release: &OsRelease) -> color_eyre::Result<Schema<'_>> {
     match os_release.id.as_str() {
         "solus" => {
             if os_release.version.name.as_ref().is_some_and(|v| v.starts_with("4.")) {
@@ -119,16 +143,8 @@ fn query_schema(os_release: &OsRelease) -> color_eyre::Result<Schema> {
     }
 }
 
-fn inspect_root(config: &Configuration) -> color_eyre::Result<()> {
-    if let Err(e) = check_permissions() {
-        log::error!("{:#}", e);
-        return Ok(());
-    }
-
-    let os_release = scan_os_release(config.root.path())?;
-    let schema = query_schema(&os_release)?;
-    log::info!("Root Schema: {schema:?}");
-
+/// Discover all kernels within the root, augmented by their `boot.json`
+fn discover_kernels(config: &Configuration, schema: &Schema) -> color_eyre::Result<Vec<Kernel>> {
     let paths = glob::glob(&format!("{}/usr/lib/kernel/*", config.root.path().display()))?
         .chain(glob::glob(&format!(
             "{}/usr/lib/kernel/*/*",
@@ -137,14 +153,6 @@ fn inspect_root(config: &Configuration) -> color_eyre::Result<()> {
         .filter_map(|f| f.ok());
     let mut kernels = schema.discover_system_kernels(paths)?;
 
-    // Future: Include other potential bootloader asset paths
-    let booty_bits = glob::glob(&format!(
-        "{}/usr/lib*/systemd/boot/efi/*.efi",
-        config.root.path().display()
-    ))?
-    .filter_map(|f| f.ok())
-    .collect::<Vec<_>>();
-
     // If a boot JSON is provided, augment the records
     for kernel in kernels.iter_mut() {
         if let Some(json) = kernel
@@ -158,6 +166,33 @@ fn inspect_root(config: &Configuration) -> color_eyre::Result<()> {
         }
     }
     log::info!("Kernels: {kernels:?}");
+
+    Ok(kernels)
+}
+
+/// Discover bootloader assets within the root
+fn bootloader_assets(config: &Configuration) -> color_eyre::Result<Vec<PathBuf>> {
+    // Future: Include other potential bootloader asset paths
+    let booty_bits = glob::glob(&format!(
+        "{}/usr/lib*/systemd/boot/efi/*.efi",
+        config.root.path().display()
+    ))?
+    .filter_map(|f| f.ok())
+    .collect::<Vec<_>>();
+    Ok(booty_bits)
+}
+
+fn inspect_root(config: &Configuration) -> color_eyre::Result<()> {
+    if let Err(e) = check_permissions() {
+        log::error!("{:#}", e);
+        return Ok(());
+    }
+
+    let os_release = scan_os_release(config.root.path())?;
+    let schema = query_schema(&os_release)?;
+    log::info!("Root Schema: {schema:?}");
+
+    let kernels = discover_kernels(config, &schema)?;
     let mut entries = kernels.iter().map(Entry::new).collect::<Vec<_>>();
     for entry in entries.iter_mut() {
         entry.load_cmdline_snippets(config)?;
@@ -166,13 +201,63 @@ fn inspect_root(config: &Configuration) -> color_eyre::Result<()> {
     // Query the manager
     let manager = Manager::new(config)?
         .with_entries(entries.into_iter())
-        .with_bootloader_assets(booty_bits);
+        .with_bootloader_assets(bootloader_assets(config)?);
     let _parts = manager.mount_partitions()?;
     eprintln!("manager = {manager:?}");
 
     Ok(())
 }
 
+/// Synchronise `$BOOT` with the kernels installed in the root
+fn update_root(config: &Configuration, verification: Verification, resume: bool, dps: bool) -> color_eyre::Result<()> {
+    check_permissions()?;
+
+    let os_release = scan_os_release(config.root.path())?;
+    let schema = query_schema(&os_release)?;
+    log::info!("Root Schema: {schema:?}");
+
+    let kernels = discover_kernels(config, &schema)?;
+    let mut entries = kernels.iter().map(Entry::new).collect::<Vec<_>>();
+    for entry in entries.iter_mut() {
+        entry.load_cmdline_snippets(config)?;
+    }
+
+    let manager = Manager::new(config)?
+        .with_entries(entries.into_iter())
+        .with_bootloader_assets(bootloader_assets(config)?)
+        .with_verification(verification)
+        .with_resume(resume)
+        .with_dps(dps);
+    let _parts = manager.mount_partitions()?;
+
+    match manager.sync(&schema) {
+        Err(blsforme::Error::Bootloader(bootloader::Error::Verification { path, expected, found })) => {
+            Err(eyre!("{} is corrupt after writing", path.display()))
+                .note(format!("expected blake3 {expected}, found {found}"))
+                .suggestion("The filesystem on $BOOT may be damaged, consider checking it with fsck.vfat")
+        }
+        result => Ok(result?),
+    }
+}
+
+/// Adopt or forget `$BOOT` files within the ownership manifest
+fn manage_ownership(config: &Configuration, paths: &[PathBuf], adopt: bool) -> color_eyre::Result<()> {
+    check_permissions()?;
+
+    let os_release = scan_os_release(config.root.path())?;
+    let schema = query_schema(&os_release)?;
+    let manager = Manager::new(config)?;
+    let _parts = manager.mount_partitions()?;
+
+    if adopt {
+        manager.adopt(&schema, paths)?;
+    } else {
+        manager.forget(&schema, paths)?;
+    }
+
+    Ok(())
+}
+
 /// Bail-out permission check for execution
 fn check_permissions() -> color_eyre::Result<()> {
     let euid = unsafe { nix::libc::geteuid() };
@@ -225,7 +310,20 @@ fn main() -> color_eyre::Result<()> {
         Commands::ReportBooted => todo!(),
         Commands::RemoveKernel => todo!(),
         Commands::MountBoot => todo!(),
-        Commands::Update => todo!(),
+        Commands::Update {
+            verify,
+            verify_writes,
+            resume,
+            dps,
+        } => update_root(
+            &config,
+            Verification {
+                compare: verify,
+                read_back: verify_writes,
+            },
+            resume,
+            dps,
+        )?,
         Commands::SetTimeout { timeout: _ } => todo!(),
         Commands::GetTimeout => todo!(),
         Commands::SetKernel { kernel: _ } => todo!(),
@@ -233,6 +331,8 @@ fn main() -> color_eyre::Result<()> {
         Commands::Status => {
             inspect_root(&config)?;
         }
+        Commands::Adopt { paths } => manage_ownership(&config, &paths, true)?,
+        Commands::Forget { paths } => manage_ownership(&config, &paths, false)?,
     }
 
     Ok(())
@@ -5,7 +5,6 @@
 //! Boot environment tracking (ESP vs XBOOTLDR, etc)
 
 use std::{
-    collections::HashMap,
     fs::{self, File},
     path::PathBuf,
 };
@@ -56,21 +55,13 @@ impl BootEnvironment {
             Firmware::BIOS
         };
 
-        let mounts = probe
-            .mounts
-            .iter()
-            .filter_map(|m| Some((fs::canonicalize(m.device).ok()?, m)))
-            .collect::<HashMap<_, _>>();
-
         // For image mode, only allow raw discovery of the GPT device. Otherwise, query BLS
         let esp = if matches!(config.root, Root::Image(_)) {
             Self::determine_esp_by_gpt(disk_parent, config).ok()
         } else if let Ok(device) = Self::determine_esp_by_bls(&firmware, config) {
             Some(device)
-        } else if let Ok(device) = Self::determine_esp_by_gpt(disk_parent, config) {
-            Some(device)
         } else {
-            None
+            Self::determine_esp_by_gpt(disk_parent, config).ok()
         };
 
         // Make sure our config is sane!
@@ -81,23 +72,25 @@ impl BootEnvironment {
             }
         }
 
-        let esp_mountpoint = esp
-            .as_ref()
-            .and_then(|e| fs::canonicalize(mounts.get(e)?.mountpoint).ok());
-
         // Report ESP and check for XBOOTLDR
         if let Some(esp_path) = esp.as_ref() {
             log::info!("EFI System Partition: {}", esp_path.display());
             let xbootldr = if let Ok(xbootldr) = Self::discover_xbootldr(probe, esp_path, config) {
                 log::info!("EFI XBOOTLDR Partition: {}", xbootldr.display());
-                Some(xbootldr)
+                Some(Self::resolve_raid(probe, xbootldr))
             } else {
                 None
             };
 
+            // Firmware reads a single member, but we must only ever write through the array
+            let esp = Some(Self::resolve_raid(probe, esp_path.clone()));
+            let esp_mountpoint = esp
+                .as_ref()
+                .and_then(|e| fs::canonicalize(probe.get_mountpoint_from_device(e)?).ok());
+
             let xboot_mountpoint = xbootldr
                 .as_ref()
-                .and_then(|e| fs::canonicalize(mounts.get(e)?.mountpoint).ok());
+                .and_then(|e| fs::canonicalize(probe.get_mountpoint_from_device(e)?).ok());
 
             Ok(Self {
                 xbootldr,
@@ -112,11 +105,37 @@ impl BootEnvironment {
                 esp,
                 firmware,
                 xboot_mountpoint: None,
-                esp_mountpoint,
+                esp_mountpoint: None,
             })
         }
     }
 
+    /// If the partition is a member of an md array, return the array device instead
+    ///
+    /// Only RAID1 with metadata 1.0 (superblock at the end) keeps each member
+    /// readable as a plain filesystem by the firmware.
+    fn resolve_raid(probe: &Probe, device: PathBuf) -> PathBuf {
+        let Some(array) = probe.get_raid_holder(&device) else {
+            return device;
+        };
+        match probe.get_raid(&array) {
+            Some(raid) if raid.level == "raid1" && raid.metadata == "1.0" => {
+                log::info!("{} is a member of RAID1 array {}", device.display(), array.display());
+            }
+            Some(raid) => {
+                log::warn!(
+                    "{} is a member of {} array {} with metadata {}, firmware may be unable to read it",
+                    device.display(),
+                    raid.level,
+                    array.display(),
+                    raid.metadata
+                );
+            }
+            None => return device,
+        }
+        array
+    }
+
     /// If UEFI we can ask BootLoaderProtocol for help to find out the ESP device.
     fn determine_esp_by_bls(firmware: &Firmware, config: &Configuration) -> Result<PathBuf, Error> {
         // UEFI only tyvm
@@ -4,11 +4,16 @@
 
 //! Bootloader APIs
 
-use std::path::{PathBuf, StripPrefixError};
+use std::path::{Path, PathBuf, StripPrefixError};
 
 use thiserror::Error;
 
-use crate::{manager::Mounts, Entry, Firmware, Kernel, Schema};
+use crate::{
+    file_utils::{copy_atomic_vfat, hash_file_uncached, write_atomic_vfat},
+    hash_cache::HashCache,
+    manager::Mounts,
+    manifest, Cmdline, Entry, Firmware, Kernel, Schema, Verification,
+};
 
 pub mod systemd_boot;
 
@@ -24,6 +29,19 @@ pub enum Error {
     #[error("io: {0}")]
     IO(#[from] std::io::Error),
 
+    #[error("manifest: {0}")]
+    Manifest(#[from] manifest::Error),
+
+    #[error("verification failed for {}: expected {expected}, found {found}", path.display())]
+    Verification {
+        path: PathBuf,
+        expected: String,
+        found: String,
+    },
+
+    #[error("not a loader entry or kernel tree: {0}")]
+    Unmanageable(PathBuf),
+
     #[error("wip: {0}")]
     Prefix(#[from] StripPrefixError),
 
@@ -31,6 +49,9 @@ pub enum Error {
     Any(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
 
+/// How many times we attempt to write a file that fails verification
+const COPY_ATTEMPTS: usize = 3;
+
 #[derive(Debug)]
 pub enum Bootloader<'a, 'b> {
     /// We really only support systemd-boot right now
@@ -53,21 +74,37 @@ impl<'a, 'b> Bootloader<'a, 'b> {
         }
     }
 
-    /// Sync bootloader to BOOT dir
-    pub fn sync(&self) -> Result<(), Error> {
+    /// Sync bootloader to BOOT dir, returning the number of files changed
+    pub fn sync(&self, cache: &mut HashCache, verification: &Verification) -> Result<usize, Error> {
         match &self {
-            Bootloader::Systemd(s) => s.sync(),
+            Bootloader::Systemd(s) => s.sync(cache, verification),
         }
     }
 
+    /// Sync entries to BOOT dir, returning the number of files changed
     pub fn sync_entries(
         &self,
-        cmdline: impl Iterator<Item = &'a str>,
+        cmdline: &Cmdline,
         entries: &[Entry],
-        excluded_snippets: impl Iterator<Item = &'a str>,
-    ) -> Result<(), Error> {
+        cache: &mut HashCache,
+        verification: &Verification,
+    ) -> Result<usize, Error> {
+        match &self {
+            Bootloader::Systemd(s) => s.sync_entries(cmdline, entries, cache, verification),
+        }
+    }
+
+    /// Take ownership of the given `$BOOT` files, permitting their removal when stale
+    pub fn adopt(&self, paths: &[impl AsRef<Path>], verification: &Verification) -> Result<(), Error> {
         match &self {
-            Bootloader::Systemd(s) => s.sync_entries(cmdline, entries, excluded_snippets),
+            Bootloader::Systemd(s) => s.adopt(paths, verification),
+        }
+    }
+
+    /// Drop ownership of the given `$BOOT` files, preventing their removal
+    pub fn forget(&self, paths: &[impl AsRef<Path>], verification: &Verification) -> Result<(), Error> {
+        match &self {
+            Bootloader::Systemd(s) => s.forget(paths, verification),
         }
     }
 
@@ -78,3 +115,93 @@ impl<'a, 'b> Bootloader<'a, 'b> {
         }
     }
 }
+
+/// Copy a file to `$BOOT`, reading it back when requested to ensure the
+/// contents match the `expected` blake3 hash. Mismatches are retried before
+/// giving up with [`Error::Verification`].
+pub(crate) fn copy_file(source: &Path, dest: &Path, expected: &str, verification: &Verification) -> Result<(), Error> {
+    write_verified(dest, expected, verification, || copy_atomic_vfat(source, dest))
+}
+
+/// Write generated contents (loader entries, the manifest) to `$BOOT`, with
+/// the same verification as [`copy_file`]
+pub(crate) fn write_file(contents: &[u8], dest: &Path, verification: &Verification) -> Result<(), Error> {
+    let expected = blake3::hash(contents).to_hex().to_string();
+    write_verified(dest, &expected, verification, || write_atomic_vfat(contents, dest))
+}
+
+/// Run `write` until `dest` reads back as `expected`, or we run out of attempts
+fn write_verified(
+    dest: &Path,
+    expected: &str,
+    verification: &Verification,
+    mut write: impl FnMut() -> Result<(), Box<dyn std::error::Error + Send + Sync>>,
+) -> Result<(), Error> {
+    let mut attempt = 1;
+    loop {
+        write()?;
+        if !verification.read_back {
+            return Ok(());
+        }
+
+        let found = hash_file_uncached(dest)?;
+        if found == expected {
+            return Ok(());
+        }
+
+        log::warn!(
+            "Verification failed for {} (attempt {attempt}/{COPY_ATTEMPTS})",
+            dest.display()
+        );
+        if attempt == COPY_ATTEMPTS {
+            return Err(Error::Verification {
+                path: dest.into(),
+                expected: expected.into(),
+                found,
+            });
+        }
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, env, fs};
+
+    use super::{write_verified, Error, COPY_ATTEMPTS};
+    use crate::Verification;
+
+    #[test]
+    fn test_write_verified() {
+        let dir = env::temp_dir().join(format!("blsforme-write-verified-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("Cannot create test dir");
+        let dest = dir.join("entry.conf");
+        let expected = blake3::hash(b"good").to_hex().to_string();
+        let verification = Verification {
+            compare: false,
+            read_back: true,
+        };
+
+        // First write lands corrupted, the retry fixes it
+        let attempts = Cell::new(0);
+        write_verified(&dest, &expected, &verification, || {
+            attempts.set(attempts.get() + 1);
+            let contents: &[u8] = if attempts.get() == 1 { b"bad" } else { b"good" };
+            Ok(fs::write(&dest, contents)?)
+        })
+        .expect("Retry should recover");
+        assert_eq!(attempts.get(), 2);
+
+        // Persistent corruption gives up after every attempt
+        attempts.set(0);
+        let err = write_verified(&dest, &expected, &verification, || {
+            attempts.set(attempts.get() + 1);
+            Ok(fs::write(&dest, b"bad")?)
+        })
+        .expect_err("Corruption should be detected");
+        assert_eq!(attempts.get(), COPY_ATTEMPTS);
+        assert!(matches!(err, Error::Verification { found, .. } if found == blake3::hash(b"bad").to_hex().to_string()));
+
+        fs::remove_dir_all(&dir).expect("Cannot clean test dir");
+    }
+}
@@ -5,14 +5,16 @@
 //! systemd-boot management and interfaces
 
 use std::{
-    fs::{self, create_dir_all},
-    path::PathBuf,
+    fs, io,
+    path::{Path, PathBuf},
 };
 
 use crate::{
-    file_utils::{changed_files, copy_atomic_vfat, PathExt},
+    file_utils::{hash_file, PathExt},
+    hash_cache::HashCache,
     manager::Mounts,
-    Entry, Kernel, Schema,
+    manifest::{self, Manifest},
+    Cmdline, Entry, Kernel, Schema, Verification,
 };
 
 pub mod interface;
@@ -31,15 +33,6 @@ pub struct Loader<'a, 'b> {
     boot_root: PathBuf,
 }
 
-#[derive(Debug)]
-struct InstallResult {
-    /// The `.conf` file that was written (absolute)
-    loader_conf: String,
-
-    // The kernel path that was installed (absolute)
-    kernel_dir: String,
-}
-
 impl<'a, 'b> Loader<'a, 'b> {
     /// Construct a new systemd boot loader manager
     pub(super) fn new(schema: &'a Schema<'a>, assets: &'b [PathBuf], mounts: &'a Mounts) -> Result<Self, super::Error> {
@@ -68,7 +61,9 @@ impl<'a, 'b> Loader<'a, 'b> {
     }
 
     /// Sync bootloader to ESP (not XBOOTLDR..)
-    pub(super) fn sync(&self) -> Result<(), super::Error> {
+    ///
+    /// Returns the number of files changed
+    pub(super) fn sync(&self, cache: &mut HashCache, verification: &Verification) -> Result<usize, super::Error> {
         let x64_efi = self
             .assets
             .iter()
@@ -82,7 +77,7 @@ impl<'a, 'b> Loader<'a, 'b> {
             .as_ref()
             .ok_or(super::Error::MissingMount("ESP (/efi)"))?;
         // Copy systemd-bootx64.efi into these locations
-        let targets = vec![
+        let targets = [
             (
                 x64_efi.clone(),
                 esp.join_insensitive("EFI")
@@ -97,86 +92,145 @@ impl<'a, 'b> Loader<'a, 'b> {
             ),
         ];
 
-        for (source, dest) in changed_files(targets.as_slice()) {
-            copy_atomic_vfat(source, dest)?;
+        // The cache spares rereading both sides unless asked to verify `$BOOT`
+        let mut changed = 0;
+        for (source, dest) in targets.iter() {
+            let hash = cache.hash(source)?;
+            let unchanged = if verification.compare {
+                hash_file(dest).is_ok_and(|h| h == hash)
+            } else {
+                cache.hash(dest).is_ok_and(|h| h == hash)
+            };
+            if !unchanged {
+                super::copy_file(source, dest, &hash, verification)?;
+                changed += 1;
+            }
         }
 
-        Ok(())
+        Ok(changed)
     }
 
+    /// Install all entries and remove any stale ones we own
+    ///
+    /// Returns the number of files changed
     pub(super) fn sync_entries(
         &self,
-        cmdline: impl Iterator<Item = &'a str>,
+        cmdline: &Cmdline,
         entries: &[Entry],
-        excluded_snippets: impl Iterator<Item = &'a str>,
-    ) -> Result<(), super::Error> {
-        let base_cmdline = cmdline.map(str::to_string).collect::<Vec<_>>();
-        let exclusions = excluded_snippets.map(str::to_string).collect::<Vec<_>>();
-        let mut installed_entries = vec![];
+        cache: &mut HashCache,
+        verification: &Verification,
+    ) -> Result<usize, super::Error> {
+        let previous = self.load_manifest()?;
+        let mut manifest = Manifest::default();
+        let mut changed = 0;
+
         for entry in entries {
-            let entry_cmdline = entry
-                .cmdline
-                .iter()
-                .filter(|c| !exclusions.contains(&c.name))
-                .map(|c| c.snippet.clone())
-                .collect::<Vec<_>>();
-            let full_cmdline = base_cmdline
-                .iter()
-                .chain(entry_cmdline.iter())
-                .cloned()
-                .collect::<Vec<_>>();
+            // Automatic cmdline, then all snippets in drop-in order
+            let mut full_cmdline = cmdline.clone();
+            for snippet in entry.cmdline_snippets() {
+                full_cmdline.merge(&Cmdline::from(snippet));
+            }
 
-            let installed = self.install(&full_cmdline.join(" "), entry)?;
-            installed_entries.push(installed);
+            changed += self.install(
+                &full_cmdline.to_string(),
+                entry,
+                &mut manifest,
+                &previous,
+                cache,
+                verification,
+            )?;
         }
 
-        let schema_prefix = match self.schema {
-            Schema::Legacy { os_release, .. } => os_release.name.clone(),
-            Schema::Blsforme { os_release } => os_release.id.clone(),
-        };
-
-        let loader_dir = self.boot_root.join_insensitive("loader").join_insensitive("entries");
-        let loader_files = fs::read_dir(loader_dir)?
-            .filter_map(|d| d.ok())
-            .filter(|f| f.file_name().to_string_lossy().to_string().starts_with(&schema_prefix))
-            .map(|f| f.path())
-            .collect::<Vec<_>>();
-
-        let kernel_dirs = fs::read_dir(&self.kernel_dir)?
-            .filter_map(|d| d.ok())
-            .filter(|f| f.file_type().map(|t| t.is_dir()).unwrap_or(false))
-            .map(|f| f.path())
-            .collect::<Vec<_>>();
-
-        let obsolete_loader_confs = loader_files
-            .iter()
-            .filter(|f| !installed_entries.iter().any(|e| e.loader_conf == f.to_string_lossy()))
+        // Only ever remove what we previously installed ourselves
+        let stale_confs = previous
+            .entries
+            .difference(&manifest.entries)
+            .cloned()
             .collect::<Vec<_>>();
-
-        let obsolete_kernels = kernel_dirs
-            .iter()
-            .filter(|f| !installed_entries.iter().any(|e| e.kernel_dir == f.to_string_lossy()))
+        let stale_kernels = previous
+            .kernel_trees
+            .difference(&manifest.kernel_trees)
+            .cloned()
             .collect::<Vec<_>>();
 
-        for conf in obsolete_loader_confs.iter() {
+        for conf in stale_confs {
             log::info!("Removing stale loader config: {conf:?}");
-            if let Err(e) = fs::remove_file(conf) {
-                log::error!("Failed to remove stale loader config {conf:?}: {e}")
+            match fs::remove_file(self.boot_root.join(&conf)) {
+                Ok(_) => changed += 1,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    log::error!("Failed to remove stale loader config {conf:?}: {e}");
+                    manifest.entries.insert(conf);
+                }
             }
         }
 
-        for tree in obsolete_kernels.iter() {
+        for tree in stale_kernels {
             log::info!("Removing stale kernel tree: {tree:?}");
-            if let Err(e) = fs::remove_dir_all(tree) {
-                log::error!("Failed to remove stale kernel tree {tree:?}: {e}")
+            match fs::remove_dir_all(self.boot_root.join(&tree)) {
+                Ok(_) => changed += 1,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    log::error!("Failed to remove stale kernel tree {tree:?}: {e}");
+                    manifest.kernel_trees.insert(tree);
+                }
             }
         }
 
-        Ok(())
+        self.save_manifest(&manifest, verification)?;
+
+        Ok(changed)
+    }
+
+    /// Take ownership of the given `$BOOT` files so they may be removed when stale
+    pub(super) fn adopt(&self, paths: &[impl AsRef<Path>], verification: &Verification) -> Result<(), super::Error> {
+        let mut manifest = self.load_manifest()?;
+        let entry_dir = self.boot_root.join_insensitive("loader").join_insensitive("entries");
+
+        for path in paths {
+            let path = self.boot_root.join(path);
+            let relative = self.relative_path(&path)?;
+            if path.is_file() && path.parent() == Some(&entry_dir) && relative.ends_with(".conf") {
+                manifest.entries.insert(relative);
+            } else if path.is_dir() && path.parent() == Some(&self.kernel_dir) {
+                manifest.kernel_trees.insert(relative);
+            } else {
+                return Err(super::Error::Unmanageable(path));
+            }
+            log::info!("Adopted {}", path.display());
+        }
+
+        self.save_manifest(&manifest, verification)
+    }
+
+    /// Drop ownership of the given `$BOOT` files so they are never removed
+    pub(super) fn forget(&self, paths: &[impl AsRef<Path>], verification: &Verification) -> Result<(), super::Error> {
+        let mut manifest = self.load_manifest()?;
+
+        for path in paths {
+            let path = self.boot_root.join(path);
+            if manifest.forget(&self.relative_path(&path)?) {
+                log::info!("Forgot {}", path.display());
+            } else {
+                log::warn!("Not owned by blsforme: {}", path.display());
+            }
+        }
+
+        self.save_manifest(&manifest, verification)
     }
 
     /// Install a kernel to the ESP or XBOOTLDR, write a config for it
-    fn install(&self, cmdline: &str, entry: &Entry) -> Result<InstallResult, super::Error> {
+    ///
+    /// Returns the number of files changed
+    fn install(
+        &self,
+        cmdline: &str,
+        entry: &Entry,
+        manifest: &mut Manifest,
+        previous: &Manifest,
+        cache: &mut HashCache,
+        verification: &Verification,
+    ) -> Result<usize, super::Error> {
         let loader_id = self
             .boot_root
             .join_insensitive("loader")
@@ -194,9 +248,8 @@ impl<'a, 'b> Loader<'a, 'b> {
         );
         // initrds requiring install
         let initrds = entry
-            .kernel
-            .initrd
-            .iter()
+            .initrds()
+            .into_iter()
             .filter_map(|asset| {
                 Some((
                     sysroot.join(&asset.path),
@@ -212,13 +265,36 @@ impl<'a, 'b> Loader<'a, 'b> {
         let mut changeset = vec![(sysroot.join(&entry.kernel.image), vmlinuz.clone())];
         changeset.extend(initrds);
 
-        // Determine which need copying now.
-        let needs_writing = changed_files(changeset.as_slice());
-        log::trace!("requires update: {needs_writing:?}");
+        // Determine which need copying now, and donate them to disk
+        let mut changed = 0;
+        for (source, dest) in changeset.iter() {
+            let relative = self.relative_path(dest)?;
+            let hash = cache.hash(source)?;
+
+            // Trust the manifest unless asked to verify, sparing a read of `$BOOT`.
+            // Only files we have no record of need comparing against the disk.
+            let unchanged = match previous.hashes.get(&relative) {
+                Some(recorded) if !verification.compare => recorded == &hash && dest.exists(),
+                _ => hash_file(dest).is_ok_and(|h| h == hash),
+            };
+
+            if unchanged {
+                log::trace!("unchanged: {}", dest.display());
+            } else {
+                log::trace!("requires update: {}", dest.display());
+                super::copy_file(source, dest, &hash, verification)?;
+                changed += 1;
+            }
 
-        // Donate them to disk
-        for (source, dest) in needs_writing {
-            copy_atomic_vfat(source, dest)?;
+            // Record ownership of everything we just installed
+            manifest.hashes.insert(relative, hash);
+        }
+        let kernel_tree = vmlinuz
+            .parent()
+            .ok_or_else(|| super::Error::MissingFile("vmlinuz parent"))?;
+        // Legacy schema installs directly into the kernel dir, which we never remove
+        if kernel_tree != self.kernel_dir {
+            manifest.kernel_trees.insert(self.relative_path(kernel_tree)?);
         }
 
         let loader_config = self.generate_entry(
@@ -231,35 +307,107 @@ impl<'a, 'b> Loader<'a, 'b> {
         );
         log::trace!("loader config: {loader_config}");
 
-        let entry_dir = self.boot_root.join_insensitive("loader").join_insensitive("entries");
-        if !entry_dir.exists() {
-            create_dir_all(entry_dir)?;
+        let conf = self.relative_path(&loader_id)?;
+        manifest.hashes.insert(
+            conf.clone(),
+            blake3::hash(loader_config.as_bytes()).to_hex().to_string(),
+        );
+        manifest.entries.insert(conf);
+
+        // Only touch the ESP when the entry actually differs
+        if fs::read(&loader_id).ok().as_deref() == Some(loader_config.as_bytes()) {
+            log::trace!("loader entry unchanged: {}", loader_id.display());
+        } else {
+            super::write_file(loader_config.as_bytes(), &loader_id, verification)?;
+            changed += 1;
         }
 
-        let tracker = InstallResult {
-            loader_conf: loader_id.to_string_lossy().to_string(),
-            kernel_dir: vmlinuz
-                .parent()
-                .ok_or_else(|| super::Error::MissingFile("vmlinuz parent"))?
-                .to_string_lossy()
-                .to_string(),
+        Ok(changed)
+    }
+
+    /// Load the ownership manifest, migrating from an unmanaged `$BOOT` on first use
+    fn load_manifest(&self) -> Result<Manifest, super::Error> {
+        match Manifest::load(self.manifest_path())? {
+            Some(manifest) => Ok(manifest),
+            None => {
+                log::info!("No ownership manifest found, adopting previously installed entries");
+                self.migrate()
+            }
+        }
+    }
+
+    /// Write the ownership manifest, unless the on-disk copy is already identical
+    fn save_manifest(&self, manifest: &Manifest, verification: &Verification) -> Result<(), super::Error> {
+        let path = self.manifest_path();
+        let text = manifest.encode()?;
+        if fs::read(&path).ok().as_deref() != Some(text.as_bytes()) {
+            super::write_file(text.as_bytes(), &path, verification)?;
+        }
+        Ok(())
+    }
+
+    /// Claim everything an earlier release installed before the manifest existed
+    ///
+    /// Kernel trees within our kernel directory are adopted, along with any
+    /// `<id>-*.conf` loader entry booting a kernel from that directory. Anything
+    /// else, such as a hand written entry for another OS, is left alone.
+    fn migrate(&self) -> Result<Manifest, super::Error> {
+        let mut manifest = Manifest::default();
+        let id = match self.schema {
+            Schema::Legacy { os_release, .. } => &os_release.name,
+            Schema::Blsforme { os_release } => &os_release.id,
         };
+        let prefix = format!("{id}-");
+        let linux = format!("linux /{}/", self.relative_path(&self.kernel_dir)?);
 
-        // TODO: Hash compare and dont obliterate!
-        fs::write(loader_id, loader_config)?;
+        let entry_dir = self.boot_root.join_insensitive("loader").join_insensitive("entries");
+        if let Ok(dir) = fs::read_dir(&entry_dir) {
+            for item in dir.flatten() {
+                let path = item.path();
+                let name = item.file_name().to_string_lossy().to_string();
+                if !item.file_type()?.is_file() || !name.starts_with(&prefix) || !name.ends_with(".conf") {
+                    continue;
+                }
+                let ours = fs::read_to_string(&path)?
+                    .lines()
+                    .any(|l| l.trim_start().starts_with(&linux));
+                if ours {
+                    log::info!("Adopted {}", path.display());
+                    manifest.entries.insert(self.relative_path(&path)?);
+                }
+            }
+        }
+
+        if let Ok(dir) = fs::read_dir(&self.kernel_dir) {
+            for item in dir.flatten() {
+                if item.file_type()?.is_dir() {
+                    log::info!("Adopted {}", item.path().display());
+                    manifest.kernel_trees.insert(self.relative_path(&item.path())?);
+                }
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Location of the ownership manifest
+    fn manifest_path(&self) -> PathBuf {
+        self.kernel_dir.join_insensitive(manifest::FILENAME)
+    }
 
-        Ok(tracker)
+    /// Path relative to `$BOOT`, as recorded in the manifest
+    fn relative_path(&self, path: &Path) -> Result<String, super::Error> {
+        Ok(path.strip_prefix(&self.boot_root)?.to_string_lossy().to_string())
     }
 
     /// Generate a usable loader config entry
     fn generate_entry(&self, asset_dir: &str, cmdline: &str, entry: &Entry) -> String {
-        let initrd = if entry.kernel.initrd.is_empty() {
+        let initrd = if entry.initrds().is_empty() {
             "\n".to_string()
         } else {
             let initrds = entry
-                .kernel
-                .initrd
-                .iter()
+                .initrds()
+                .into_iter()
                 .filter_map(|asset| {
                     Some(format!(
                         "\ninitrd /{asset_dir}/{}",
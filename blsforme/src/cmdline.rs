@@ -0,0 +1,290 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Kernel command line model
+//!
+//! Each cmdline snippet is parsed into a [`Cmdline`] layer, and layers are
+//! merged in order of precedence to produce the final `options` line:
+//!
+//!  - `key=value` (or `flag`) overrides every earlier value of `key`
+//!  - `+key=value` appends, keeping any earlier values of `key`
+//!  - `-key` removes every earlier value of `key`, `-key=value` only that value
+//!  - Anything after `--` is passed through to init
+//!
+//! Exact duplicates are dropped, so rendering is stable regardless of how
+//! many snippets repeat i.e. `quiet`.
+
+use std::{convert::Infallible, fmt, str::FromStr};
+
+/// How a parameter is applied when merging layers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Override any earlier values
+    Set,
+
+    /// Keep earlier values
+    Append,
+
+    /// Remove earlier values
+    Remove,
+}
+
+/// A single kernel parameter, i.e. `console=ttyS0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub operation: Operation,
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl Parameter {
+    /// Returns true if both parameters have the same key and value
+    fn same(&self, other: &Parameter) -> bool {
+        self.key == other.key && self.value == other.value
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operation {
+            Operation::Set => {}
+            Operation::Append => f.write_str("+")?,
+            Operation::Remove => f.write_str("-")?,
+        }
+        f.write_str(&self.key)?;
+        match &self.value {
+            Some(value) if value.is_empty() || value.contains(char::is_whitespace) => write!(f, "=\"{value}\""),
+            Some(value) => write!(f, "={value}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A parsed kernel command line (or a layer of one)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cmdline {
+    params: Vec<Parameter>,
+    init: Vec<String>,
+}
+
+impl Cmdline {
+    /// Iterate the kernel parameters
+    pub fn params(&self) -> impl Iterator<Item = &Parameter> {
+        self.params.iter()
+    }
+
+    /// Arguments passed through to init (after `--`)
+    pub fn init_args(&self) -> &[String] {
+        &self.init
+    }
+
+    /// Returns true if there are no parameters or init arguments
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty() && self.init.is_empty()
+    }
+
+    /// Merge a higher precedence layer into this cmdline
+    pub fn merge(&mut self, layer: &Cmdline) {
+        // Slot per parameter so overrides replace in place
+        let mut slots = self.params.drain(..).map(|p| vec![p]).collect::<Vec<_>>();
+
+        let mut overridden: Vec<&str> = vec![];
+        for param in layer.params.iter().filter(|p| p.operation == Operation::Set) {
+            if overridden.contains(&param.key.as_str()) {
+                continue;
+            }
+            overridden.push(&param.key);
+            let values = layer
+                .params
+                .iter()
+                .filter(|p| p.operation == Operation::Set && p.key == param.key)
+                .cloned();
+
+            let first = slots.iter().position(|s| s.iter().any(|p| p.key == param.key));
+            for slot in slots.iter_mut() {
+                slot.retain(|p| p.key != param.key);
+            }
+            match first {
+                Some(index) => slots[index].extend(values),
+                None => slots.push(values.collect()),
+            }
+        }
+
+        let mut params = slots.into_iter().flatten().collect::<Vec<_>>();
+        for param in layer.params.iter() {
+            match (param.operation, &param.value) {
+                (Operation::Set, _) => {}
+                (Operation::Append, _) => params.push(Parameter {
+                    operation: Operation::Set,
+                    ..param.clone()
+                }),
+                (Operation::Remove, None) => params.retain(|p| p.key != param.key),
+                (Operation::Remove, Some(_)) => params.retain(|p| !p.same(param)),
+            }
+        }
+
+        self.params = vec![];
+        for param in params {
+            self.push(param);
+        }
+        for arg in layer.init.iter() {
+            if !self.init.contains(arg) {
+                self.init.push(arg.clone());
+            }
+        }
+    }
+
+    /// Add a parameter, dropping exact duplicates
+    fn push(&mut self, param: Parameter) {
+        if !self
+            .params
+            .iter()
+            .any(|p| p.same(&param) && p.operation == param.operation)
+        {
+            self.params.push(param);
+        }
+    }
+}
+
+/// Split on whitespace, honouring double quotes
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut started = false;
+
+    for c in s.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                started = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if started {
+                    tokens.push(std::mem::take(&mut current));
+                    started = false;
+                }
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if started {
+        tokens.push(current);
+    }
+
+    tokens
+}
+
+/// Parsing never fails: anything that isn't a recognised operation is a plain parameter
+impl From<&str> for Cmdline {
+    fn from(s: &str) -> Self {
+        let mut cmdline = Cmdline::default();
+        let mut tokens = tokenize(s).into_iter();
+
+        for token in tokens.by_ref() {
+            if token == "--" {
+                break;
+            }
+            let (operation, token) = if let Some(t) = token.strip_prefix('-') {
+                (Operation::Remove, t)
+            } else if let Some(t) = token.strip_prefix('+') {
+                (Operation::Append, t)
+            } else {
+                (Operation::Set, token.as_str())
+            };
+            let (key, value) = match token.split_once('=') {
+                Some((k, v)) => (k, Some(v.to_owned())),
+                None => (token, None),
+            };
+            if key.is_empty() {
+                continue;
+            }
+            cmdline.push(Parameter {
+                operation,
+                key: key.to_owned(),
+                value,
+            });
+        }
+        cmdline.init.extend(tokens);
+
+        cmdline
+    }
+}
+
+impl FromStr for Cmdline {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from(s))
+    }
+}
+
+impl fmt::Display for Cmdline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = self.params.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        if !self.init.is_empty() {
+            parts.push("--".into());
+            parts.extend(self.init.iter().map(|a| {
+                if a.contains(char::is_whitespace) {
+                    format!("\"{a}\"")
+                } else {
+                    a.clone()
+                }
+            }));
+        }
+        f.write_str(&parts.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cmdline;
+
+    fn parse(s: &str) -> Cmdline {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let cmdline = parse(r#"root=UUID=abc  rw quiet dyndbg="file foo.c +p" -- single "init arg""#);
+        assert_eq!(cmdline.params().count(), 4);
+        assert_eq!(cmdline.init_args(), ["single", "init arg"]);
+        assert_eq!(
+            cmdline.to_string(),
+            r#"root=UUID=abc rw quiet dyndbg="file foo.c +p" -- single "init arg""#
+        );
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut cmdline = parse("root=UUID=abc rw quiet console=tty0 console=ttyS0");
+        cmdline.merge(&parse("quiet splash console=ttyS1,115200"));
+        assert_eq!(
+            cmdline.to_string(),
+            "root=UUID=abc rw quiet console=ttyS1,115200 splash"
+        );
+
+        cmdline.merge(&parse("-quiet -splash=1 +console=tty0 rd.luks.uuid=a rd.luks.uuid=b"));
+        assert_eq!(
+            cmdline.to_string(),
+            "root=UUID=abc rw console=ttyS1,115200 splash rd.luks.uuid=a rd.luks.uuid=b console=tty0"
+        );
+
+        cmdline.merge(&parse("-console -- emergency"));
+        assert_eq!(
+            cmdline.to_string(),
+            "root=UUID=abc rw splash rd.luks.uuid=a rd.luks.uuid=b -- emergency"
+        );
+    }
+
+    #[test]
+    fn test_layer_roundtrip() {
+        let layer = parse("-quiet +console=tty0 debug");
+        assert_eq!(layer.to_string(), "-quiet +console=tty0 debug");
+        assert_eq!(parse(&layer.to_string()), layer);
+    }
+}
@@ -2,12 +2,12 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-use std::path::PathBuf;
+use std::{collections::BTreeMap, ffi::OsStr, path::PathBuf};
 
-use crate::{file_utils::cmdline_snippet, AuxiliaryFile, Configuration, Kernel, Schema};
+use crate::{file_utils::cmdline_snippet, AuxiliaryFile, AuxiliaryKind, Configuration, CpuVendor, Kernel, Schema};
 
 /// A cmdline entry is found in the `$sysroot/usr/lib/kernel/cmdline.d` directory
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CmdlineEntry {
     /// Name of the entry, i.e. `00-quiet.cmdline`
     pub name: String,
@@ -16,9 +16,53 @@ pub struct CmdlineEntry {
     pub snippet: String,
 }
 
+/// A snippet from `/etc/kernel/cmdline.d`, optionally scoped to a subdirectory
+/// named after a kernel variant (i.e. `lts`) or a version glob (i.e. `6.8.*`)
+#[derive(Debug, Clone)]
+pub(crate) struct LocalCmdline {
+    /// Subdirectory restricting the kernels this snippet applies to
+    pub(crate) scope: Option<String>,
+
+    /// Name of the snippet, i.e. `50-debug.cmdline`
+    pub(crate) name: String,
+
+    /// Contents of the snippet, or `None` when masked by a `/dev/null` symlink
+    pub(crate) snippet: Option<String>,
+}
+
+/// Match a shell style glob supporting `*` and `?`
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == b'?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp + 1;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
 /// An entry corresponds to a single kernel, and may have a supplemental
 /// cmdline
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Entry<'a> {
     pub(crate) kernel: &'a Kernel,
 
@@ -26,6 +70,24 @@ pub struct Entry<'a> {
 
     pub(crate) cmdline: Vec<CmdlineEntry>,
 
+    /// Local cmdline snippets and masks applicable to this kernel
+    pub(crate) local_cmdline: Vec<LocalCmdline>,
+
+    /// `/etc/kernel/cmdline`, applied after every other snippet
+    pub(crate) etc_cmdline: Option<String>,
+
+    /// Locally generated initrds, see [`crate::generator`]
+    pub(crate) generated_initrds: Vec<AuxiliaryFile>,
+
+    /// Local initrds (`/etc/kernel/initrd.d`), replacing any global initrd of the same name
+    pub(crate) local_initrds: Vec<AuxiliaryFile>,
+
+    /// Names of vendor initrds masked by `/dev/null` symlinks in `/etc/kernel/initrd.d`
+    pub(crate) masked_initrds: Vec<String>,
+
+    /// Only include microcode for this CPU vendor, or all microcode if unset
+    pub(crate) cpu_vendor: Option<CpuVendor>,
+
     /// Unique state ID for this entry
     pub(crate) state_id: Option<i32>,
 }
@@ -36,15 +98,41 @@ impl<'a> Entry<'a> {
         Self {
             kernel,
             cmdline: vec![],
+            local_cmdline: vec![],
+            etc_cmdline: None,
+            generated_initrds: vec![],
+            local_initrds: vec![],
+            masked_initrds: vec![],
+            cpu_vendor: None,
             sysroot: None,
             state_id: None,
         }
     }
 
     /// Load cmdline snippets from the system root for this entry's sysroot
+    ///
+    /// Per-kernel snippets replace global snippets of the same name
     pub fn load_cmdline_snippets(&mut self, config: &Configuration) -> Result<(), super::Error> {
         let sysroot = self.sysroot.clone().unwrap_or(config.root.path().into());
 
+        // Globals
+        let cmdline_d = sysroot.join("usr").join("lib").join("kernel").join("cmdline.d");
+        if cmdline_d.exists() {
+            let mut entries = std::fs::read_dir(&cmdline_d)?
+                .filter_map(Result::ok)
+                .map(|e| e.path())
+                .collect::<Vec<_>>();
+            entries.sort();
+
+            for entry in entries {
+                let name = entry.file_name().unwrap_or_default().to_string_lossy().to_string();
+                // Don't bomb out on invalid cmdline snippets
+                if let Ok(snippet) = cmdline_snippet(&entry) {
+                    self.cmdline.push(CmdlineEntry { name, snippet });
+                }
+            }
+        }
+
         // Load local cmdline snippets for this kernel entry
         for snippet in self
             .kernel
@@ -60,24 +148,26 @@ impl<'a> Entry<'a> {
             }
         }
 
-        // Globals
-        let cmdline_d = sysroot.join("usr").join("lib").join("kernel").join("cmdline.d");
-
-        if !cmdline_d.exists() {
-            return Ok(());
-        }
-
-        let entries = std::fs::read_dir(&cmdline_d)?;
+        Ok(())
+    }
 
-        for entry in entries.filter_map(Result::ok) {
-            let name = entry.file_name().to_string_lossy().to_string();
-            // Don't bomb out on invalid cmdline snippets
-            if let Ok(snippet) = cmdline_snippet(entry.path()) {
-                self.cmdline.push(CmdlineEntry { name, snippet });
-            }
+    /// All cmdline snippets for this entry in application order
+    ///
+    /// Snippets are ordered by filename, with local snippets replacing (or masking)
+    /// vendor snippets of the same name. `/etc/kernel/cmdline` always comes last.
+    pub(crate) fn cmdline_snippets(&self) -> Vec<&str> {
+        let mut snippets = self
+            .cmdline
+            .iter()
+            .map(|c| (c.name.as_str(), c.snippet.as_str()))
+            .collect::<BTreeMap<_, _>>();
+        for local in self.local_cmdline.iter() {
+            match &local.snippet {
+                Some(snippet) => snippets.insert(&local.name, snippet),
+                None => snippets.remove(local.name.as_str()),
+            };
         }
-
-        Ok(())
+        snippets.into_values().chain(self.etc_cmdline.as_deref()).collect()
     }
 
     /// With the given system root
@@ -106,6 +196,86 @@ impl<'a> Entry<'a> {
         Self { cmdline, ..self }
     }
 
+    /// With the given local cmdline snippets, keeping only those scoped to this kernel
+    ///
+    /// Scoped snippets take precedence over unscoped snippets of the same name.
+    pub(crate) fn with_local_cmdline(self, local: &[LocalCmdline]) -> Self {
+        let applies =
+            |scope: &str| self.kernel.variant.as_deref() == Some(scope) || glob_match(scope, &self.kernel.version);
+        let local_cmdline = local
+            .iter()
+            .filter(|l| l.scope.is_none())
+            .chain(local.iter().filter(|l| l.scope.as_deref().is_some_and(applies)))
+            .cloned()
+            .collect();
+        Self { local_cmdline, ..self }
+    }
+
+    /// With the given `/etc/kernel/cmdline` contents, applied as the final layer
+    pub(crate) fn with_etc_cmdline(self, etc_cmdline: Option<String>) -> Self {
+        Self { etc_cmdline, ..self }
+    }
+
+    /// With the given local initrds and masks, i.e. from `/etc/kernel/initrd.d`
+    pub(crate) fn with_local_initrds(self, local: &[AuxiliaryFile], masked: &[String]) -> Self {
+        Self {
+            local_initrds: local.to_vec(),
+            masked_initrds: masked.to_vec(),
+            ..self
+        }
+    }
+
+    /// With the given generated initrds, loaded after the vendor initrds
+    pub(crate) fn with_generated_initrds(self, generated_initrds: Vec<AuxiliaryFile>) -> Self {
+        Self {
+            generated_initrds,
+            ..self
+        }
+    }
+
+    /// Restrict microcode initrds to the given CPU vendor, or include all if unset
+    pub(crate) fn with_cpu_vendor(self, cpu_vendor: Option<CpuVendor>) -> Self {
+        Self { cpu_vendor, ..self }
+    }
+
+    /// All initrds for this entry in load order, after applying local overrides and masks
+    ///
+    /// Layers are applied from least to most specific: global `initrd.d` entries,
+    /// local `/etc/kernel/initrd.d` entries, then the kernel's own (and generated)
+    /// initrds, each replacing any earlier initrd of the same filename. Initrds are
+    /// ordered by filename regardless of their source directory, with microcode
+    /// initrds always loaded first.
+    pub fn initrds(&self) -> Vec<&AuxiliaryFile> {
+        let kernel_dir = self.kernel.image.parent();
+        let (versioned, global): (Vec<_>, Vec<_>) =
+            self.kernel.initrd.iter().partition(|i| i.path.parent() == kernel_dir);
+
+        let mut by_name = BTreeMap::new();
+        for initrd in global
+            .into_iter()
+            .chain(self.local_initrds.iter())
+            .chain(versioned)
+            .chain(self.generated_initrds.iter())
+        {
+            if let Some(name) = initrd.path.file_name() {
+                by_name.insert(name, initrd);
+            }
+        }
+        for masked in self.masked_initrds.iter() {
+            by_name.remove(OsStr::new(masked));
+        }
+
+        let mut initrds = by_name
+            .into_values()
+            .filter(|i| match (&i.kind, self.cpu_vendor) {
+                (AuxiliaryKind::Microcode(v), Some(cpu)) => *v == cpu,
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        initrds.sort_by_key(|i| !matches!(i.kind, AuxiliaryKind::Microcode(_)));
+        initrds
+    }
+
     /// Return an entry ID, suitable for `.conf` generation
     pub fn id(&self, schema: &Schema) -> String {
         // TODO: For BLS schema, grab something even uniquer (TM)
@@ -139,7 +309,7 @@ impl<'a> Entry<'a> {
     pub fn installed_asset_name(&self, schema: &Schema, asset: &AuxiliaryFile) -> Option<String> {
         match &schema {
             Schema::Legacy { .. } => match asset.kind {
-                crate::AuxiliaryKind::InitRD => asset
+                crate::AuxiliaryKind::InitRD | crate::AuxiliaryKind::Microcode(_) => asset
                     .path
                     .file_name()
                     .map(|f| f.to_string_lossy())
@@ -149,10 +319,163 @@ impl<'a> Entry<'a> {
             Schema::Blsforme { .. } => {
                 let filename = asset.path.file_name().map(|f| f.to_string_lossy())?;
                 match asset.kind {
-                    crate::AuxiliaryKind::InitRD => Some(format!("{}/{}", &self.kernel.version, filename)),
+                    crate::AuxiliaryKind::InitRD | crate::AuxiliaryKind::Microcode(_) => {
+                        Some(format!("{}/{}", &self.kernel.version, filename))
+                    }
                     _ => None,
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::{AuxiliaryFile, AuxiliaryKind, CpuVendor, Kernel};
+
+    use super::{glob_match, CmdlineEntry, Entry, LocalCmdline};
+
+    fn initrd(path: &str) -> AuxiliaryFile {
+        AuxiliaryFile {
+            path: PathBuf::from(path),
+            kind: AuxiliaryKind::for_initrd(path),
+        }
+    }
+
+    #[test]
+    fn test_local_initrds() {
+        let kernel = Kernel {
+            version: "6.8.9-289.current".into(),
+            image: "/usr/lib/kernel/6.8.9-289.current/vmlinuz".into(),
+            initrd: vec![
+                initrd("/usr/lib/kernel/6.8.9-289.current/10-default.initrd"),
+                initrd("/usr/lib/kernel/initrd.d/01-firmware.initrd"),
+                initrd("/usr/lib/kernel/initrd.d/02-vendor.initrd"),
+            ],
+            extras: vec![],
+            variant: None,
+        };
+        let local = [
+            initrd("/etc/kernel/initrd.d/01-firmware.initrd"),
+            initrd("/etc/kernel/initrd.d/50-keys.initrd"),
+        ];
+        let entry = Entry::new(&kernel).with_local_initrds(&local, &["02-vendor.initrd".into()]);
+        let initrds = entry.initrds().iter().map(|i| i.path.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            initrds,
+            vec![
+                PathBuf::from("/etc/kernel/initrd.d/01-firmware.initrd"),
+                PathBuf::from("/usr/lib/kernel/6.8.9-289.current/10-default.initrd"),
+                PathBuf::from("/etc/kernel/initrd.d/50-keys.initrd"),
+            ]
+        );
+
+        // The kernel's own initrd wins over global ones of the same name
+        let local = [initrd("/etc/kernel/initrd.d/10-default.initrd")];
+        let entry = Entry::new(&kernel).with_local_initrds(&local, &[]);
+        let initrds = entry.initrds().iter().map(|i| i.path.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            initrds,
+            vec![
+                PathBuf::from("/usr/lib/kernel/initrd.d/01-firmware.initrd"),
+                PathBuf::from("/usr/lib/kernel/initrd.d/02-vendor.initrd"),
+                PathBuf::from("/usr/lib/kernel/6.8.9-289.current/10-default.initrd"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_microcode_first() {
+        let kernel = Kernel {
+            version: "6.8.9-289.current".into(),
+            image: "/usr/lib/kernel/6.8.9-289.current/vmlinuz".into(),
+            initrd: vec![
+                initrd("/usr/lib/kernel/6.8.9-289.current/10-default.initrd"),
+                initrd("/usr/lib/kernel/initrd.d/00-amd-ucode.initrd"),
+                initrd("/usr/lib/kernel/initrd.d/00-intel-ucode.initrd"),
+            ],
+            extras: vec![],
+            variant: None,
+        };
+
+        let entry = Entry::new(&kernel);
+        let initrds = entry.initrds().iter().map(|i| i.path.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            initrds,
+            vec![
+                PathBuf::from("/usr/lib/kernel/initrd.d/00-amd-ucode.initrd"),
+                PathBuf::from("/usr/lib/kernel/initrd.d/00-intel-ucode.initrd"),
+                PathBuf::from("/usr/lib/kernel/6.8.9-289.current/10-default.initrd"),
+            ]
+        );
+
+        let entry = Entry::new(&kernel).with_cpu_vendor(Some(CpuVendor::Intel));
+        let initrds = entry.initrds().iter().map(|i| i.path.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            initrds,
+            vec![
+                PathBuf::from("/usr/lib/kernel/initrd.d/00-intel-ucode.initrd"),
+                PathBuf::from("/usr/lib/kernel/6.8.9-289.current/10-default.initrd"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_glob() {
+        assert!(glob_match("6.8.*", "6.8.9-289.current"));
+        assert!(glob_match("*.lts", "6.6.30-250.lts"));
+        assert!(glob_match("6.?.*-*.lts", "6.6.30-250.lts"));
+        assert!(!glob_match("6.8.*", "6.6.30-250.lts"));
+        assert!(!glob_match("lts", "6.6.30-250.lts"));
+    }
+
+    #[test]
+    fn test_cmdline_drop_ins() {
+        let kernel = Kernel {
+            version: "6.6.30-250.lts".into(),
+            image: "/usr/lib/kernel/6.6.30-250.lts/vmlinuz".into(),
+            initrd: vec![],
+            extras: vec![],
+            variant: Some("lts".into()),
+        };
+        let snippet = |name: &str, snippet: &str| CmdlineEntry {
+            name: name.into(),
+            snippet: snippet.into(),
+        };
+        let local = |scope: Option<&str>, name: &str, snippet: Option<&str>| LocalCmdline {
+            scope: scope.map(Into::into),
+            name: name.into(),
+            snippet: snippet.map(Into::into),
+        };
+
+        let mut entry = Entry::new(&kernel)
+            .with_local_cmdline(&[
+                local(Some("lts"), "50-debug.cmdline", Some("debug")),
+                local(None, "99-global.cmdline", Some("loglevel=3")),
+                local(None, "20-masked.cmdline", None),
+                local(None, "00-local.cmdline", Some("console=ttyS0")),
+                local(None, "50-debug.cmdline", Some("nodebug")),
+                local(Some("6.8.*"), "60-mainline.cmdline", Some("mainline")),
+                local(None, "zz-late.cmdline", Some("loglevel=7")),
+            ])
+            .with_etc_cmdline(Some("loglevel=4".into()));
+        entry.cmdline = vec![
+            snippet("99-global.cmdline", "quiet"),
+            snippet("10-default.cmdline", "splash"),
+            snippet("20-masked.cmdline", "nomodeset"),
+        ];
+        assert_eq!(
+            entry.cmdline_snippets(),
+            [
+                "console=ttyS0",
+                "splash",
+                "debug",
+                "loglevel=3",
+                "loglevel=7",
+                "loglevel=4"
+            ]
+        );
+    }
+}
@@ -6,11 +6,13 @@
 
 use std::{
     fs::{self, create_dir_all, File},
-    io,
+    io::{self, Read},
     os::{fd::AsRawFd, unix::fs::MetadataExt},
     path::{Path, PathBuf},
 };
 
+use nix::fcntl::{posix_fadvise, PosixFadviseAdvice};
+
 use crate::Error;
 
 /// Case-insensitive path joining for FAT, respecting existing entries on the filesystem
@@ -58,6 +60,23 @@ fn files_identical(hasher: &mut blake3::Hasher, a: &Path, b: &Path) -> Result<bo
     }
 }
 
+/// Compute the blake3 hash of a file, returned as a hex string
+pub fn hash_file(path: impl AsRef<Path>) -> Result<String, io::Error> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_mmap_rayon(path)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Compute the blake3 hash of a file after evicting it from the page cache,
+/// ensuring we hash what actually landed on the underlying device.
+pub fn hash_file_uncached(path: impl AsRef<Path>) -> Result<String, io::Error> {
+    let file = File::open(path)?;
+    posix_fadvise(file.as_raw_fd(), 0, 0, PosixFadviseAdvice::POSIX_FADV_DONTNEED)?;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_reader(&file)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
 /// Find out which files in the set changed
 ///
 /// Given a slice containing tuples of pathbufs, return an
@@ -93,10 +112,20 @@ pub fn copy_atomic_vfat(
     source: impl AsRef<Path>,
     dest: impl AsRef<Path>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let source = source.as_ref();
+    let input = File::open(source.as_ref())?;
+    write_atomic_vfat(input, dest)
+}
+
+/// Write the contents of a reader to dest file, handling vfat oddities.
+///
+/// See [`copy_atomic_vfat`] for the staging semantics.
+pub fn write_atomic_vfat(
+    mut input: impl Read,
+    dest: impl AsRef<Path>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let dest = dest.as_ref();
 
-    log::trace!("copy_atomic_vfat: {}", dest.display());
+    log::trace!("write_atomic_vfat: {}", dest.display());
 
     // Staging path
     let dest_temp = dest.with_extension(".TmpWrite");
@@ -114,7 +143,6 @@ pub fn copy_atomic_vfat(
         .write(true)
         .create(true)
         .open(&dest_temp)?;
-    let mut input = File::open(source)?;
 
     let output_fd = output.as_raw_fd();
 
@@ -0,0 +1,329 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Local initrd generation
+//!
+//! Kernels without a prebuilt initrd (or users with custom modules and keys)
+//! can have one generated per kernel by dracut, mkinitcpio or any custom tool.
+//! Results are cached under `/var/cache/blsforme/initrd` and only regenerated
+//! when the kernel, the generator or its configuration changes.
+
+use std::{
+    fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{self, ExitStatus},
+};
+
+use thiserror::Error;
+
+use crate::{hash_cache::HashCache, AuxiliaryFile, AuxiliaryKind, Kernel};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("i/o error: {0}")]
+    IO(#[from] io::Error),
+
+    #[error("initrd generator {name} failed: {status}")]
+    Failed { name: String, status: ExitStatus },
+
+    #[error("initrd generator {0} did not produce an initrd")]
+    MissingOutput(String),
+}
+
+/// An initrd generator, invoked for each kernel during sync
+pub trait Generator: std::fmt::Debug {
+    /// Unique name of the generator, used for the `{name}.initrd` output
+    fn name(&self) -> &str;
+
+    /// Any files influencing the output, besides the kernel itself. Directories
+    /// (i.e. `/etc/dracut.conf.d`) cover every file within, and missing paths
+    /// are tracked too, so creating one regenerates the initrd.
+    fn inputs(&self) -> Vec<PathBuf>;
+
+    /// Arguments passed to the generator, which influence the output just as much
+    fn args(&self) -> &[String] {
+        &[]
+    }
+
+    /// Generate an initrd for the kernel version at `output`
+    fn generate(&self, version: &str, image: &Path, output: &Path) -> Result<(), Error>;
+
+    /// Whether the kernel requires a generated initrd. By default only
+    /// kernels lacking a prebuilt initrd are handled. Global `initrd.d`
+    /// entries are appended to every kernel, so only the kernel's own
+    /// versioned directory counts.
+    fn applies_to(&self, kernel: &Kernel) -> bool {
+        let kernel_dir = kernel.image.parent();
+        !kernel
+            .initrd
+            .iter()
+            .any(|i| matches!(i.kind, AuxiliaryKind::InitRD) && i.path.parent() == kernel_dir)
+    }
+}
+
+/// Runs an external command as `program [args..] KERNEL_VERSION OUTPUT`
+///
+/// `KERNEL_IMAGE` is set in the environment to the kernel image path.
+#[derive(Debug)]
+pub struct CommandGenerator {
+    name: String,
+    program: PathBuf,
+    args: Vec<String>,
+    config: Vec<PathBuf>,
+}
+
+impl CommandGenerator {
+    /// New generator for the given program
+    pub fn new(name: impl Into<String>, program: impl Into<PathBuf>) -> Self {
+        Self {
+            name: name.into(),
+            program: program.into(),
+            args: vec![],
+            config: vec![],
+        }
+    }
+
+    /// With the given leading arguments
+    pub fn with_args(self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            args: args.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// With the given configuration files or directories, i.e. `/etc/dracut.conf.d`
+    pub fn with_config(self, config: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        Self {
+            config: config.into_iter().map(Into::into).collect(),
+            ..self
+        }
+    }
+
+    /// Discover generators within `/etc/kernel/initrd-generators.d` of the root
+    ///
+    /// Every executable file is a generator, named after its filename. An
+    /// optional `{name}.config` file lists the generator's configuration paths,
+    /// one absolute path (within the root) per line.
+    pub fn discover(root: impl AsRef<Path>) -> Vec<Self> {
+        let root = root.as_ref();
+        let dir = root.join("etc").join("kernel").join("initrd-generators.d");
+        let mut generators = fs::read_dir(&dir)
+            .map(|i| {
+                i.filter_map(|p| p.ok())
+                    .filter(|d| {
+                        d.path()
+                            .metadata()
+                            .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                    })
+                    .map(|d| {
+                        let name = d.file_name().to_string_lossy().to_string();
+                        let config = fs::read_to_string(dir.join(format!("{name}.config")))
+                            .unwrap_or_default()
+                            .lines()
+                            .map(str::trim)
+                            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                            .map(|l| root.join(l.trim_start_matches('/')))
+                            .collect::<Vec<_>>();
+                        Self::new(name, d.path()).with_config(config)
+                    })
+            })
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        generators.sort_by(|a, b| a.name.cmp(&b.name));
+        generators
+    }
+}
+
+impl Generator for CommandGenerator {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn inputs(&self) -> Vec<PathBuf> {
+        std::iter::once(self.program.clone())
+            .chain(self.config.iter().cloned())
+            .collect()
+    }
+
+    fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    fn generate(&self, version: &str, image: &Path, output: &Path) -> Result<(), Error> {
+        log::info!("Generating initrd {} for {version}", self.name);
+        let status = process::Command::new(&self.program)
+            .args(&self.args)
+            .arg(version)
+            .arg(output)
+            .env("KERNEL_IMAGE", image)
+            .status()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::Failed {
+                name: self.name.clone(),
+                status,
+            })
+        }
+    }
+}
+
+/// Stamp a single generator input: its hash, every file within for directories,
+/// or its absence
+fn stamp_input(stamp: &mut String, input: &Path, hashes: &mut HashCache) -> Result<(), io::Error> {
+    if input.is_dir() {
+        let mut entries = fs::read_dir(input)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.sort();
+        for entry in entries.iter().filter(|e| e.is_file()) {
+            stamp.push_str(&format!("{} {}\n", entry.display(), hashes.hash(entry)?));
+        }
+    } else if input.exists() {
+        stamp.push_str(&format!("{} {}\n", input.display(), hashes.hash(input)?));
+    } else {
+        stamp.push_str(&format!("{} -\n", input.display()));
+    }
+    Ok(())
+}
+
+/// Produce the initrd for a kernel, reusing the cached copy in `cache_dir`
+/// unless the kernel image, generator inputs or arguments have changed.
+pub(crate) fn generate_cached(
+    generator: &dyn Generator,
+    kernel: &Kernel,
+    image: &Path,
+    cache_dir: &Path,
+    hashes: &mut HashCache,
+) -> Result<AuxiliaryFile, super::Error> {
+    let dir = cache_dir.join(&kernel.version);
+    let output = dir.join(format!("{}.initrd", generator.name()));
+    let stamp_path = dir.join(format!("{}.stamp", generator.name()));
+
+    // Everything that influences the output
+    let mut stamp = format!("{}\n{}\n", kernel.version, hashes.hash(image)?);
+    for input in generator.inputs() {
+        stamp_input(&mut stamp, &input, hashes)?;
+    }
+    stamp.push_str(&format!("{:?}\n", generator.args()));
+
+    if output.exists() && fs::read_to_string(&stamp_path).is_ok_and(|s| s == stamp) {
+        log::trace!("using cached initrd: {}", output.display());
+    } else {
+        fs::create_dir_all(&dir)?;
+        let _ = fs::remove_file(&stamp_path);
+        let staging = output.with_extension("initrd.tmp");
+        generator.generate(&kernel.version, image, &staging)?;
+        if !staging.exists() {
+            return Err(Error::MissingOutput(generator.name().to_owned()).into());
+        }
+        fs::rename(&staging, &output)?;
+        fs::write(&stamp_path, stamp)?;
+    }
+
+    Ok(AuxiliaryFile {
+        path: output,
+        kind: AuxiliaryKind::InitRD,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+
+    use crate::{hash_cache::HashCache, AuxiliaryFile, AuxiliaryKind, Kernel};
+
+    use super::{generate_cached, CommandGenerator, Generator};
+
+    #[test]
+    fn test_stub_generator() {
+        let dir = env::temp_dir().join(format!("blsforme-generator-{}", std::process::id()));
+        let gen_dir = dir.join("etc").join("kernel").join("initrd-generators.d");
+        let cache_dir = dir.join("cache");
+        fs::create_dir_all(&gen_dir).expect("Cannot create test dir");
+
+        // Stub generator records each invocation. It runs from the test dir, so even
+        // a relative output path can never land in the crate tree.
+        let script = gen_dir.join("50-stub");
+        let log = dir.join("invocations");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\ncd \"{}\" || exit 1\nshift $(($# - 2))\necho \"$1\" >> \"{}\"\necho \"initrd for $1\" > \"$2\"\n",
+                dir.display(),
+                log.display()
+            ),
+        )
+        .expect("Cannot write stub");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).expect("Cannot chmod stub");
+        fs::write(gen_dir.join("README"), "not executable").expect("Cannot write README");
+        fs::write(gen_dir.join("50-stub.config"), "# stub config\n/etc/stub.conf.d\n").expect("Cannot write config");
+        let conf_dir = dir.join("etc").join("stub.conf.d");
+
+        let image = dir.join("vmlinuz");
+        fs::write(&image, "kernel").expect("Cannot write kernel");
+        let kernel = Kernel {
+            version: "6.8.9-289.current".into(),
+            image: image.clone(),
+            initrd: vec![],
+            extras: vec![],
+            variant: None,
+        };
+
+        let generators = CommandGenerator::discover(&dir);
+        assert_eq!(generators.len(), 1);
+        let generator = &generators[0];
+        assert_eq!(generator.name(), "50-stub");
+        assert_eq!(generator.inputs(), vec![script.clone(), conf_dir.clone()]);
+        assert!(generator.applies_to(&kernel));
+
+        let mut hashes = HashCache::default();
+        let initrd = generate_cached(generator, &kernel, &image, &cache_dir, &mut hashes).expect("Generation failed");
+        assert_eq!(initrd.path, cache_dir.join("6.8.9-289.current").join("50-stub.initrd"));
+        assert_eq!(
+            fs::read_to_string(&initrd.path).expect("Missing initrd"),
+            "initrd for 6.8.9-289.current\n"
+        );
+
+        // Cached
+        generate_cached(generator, &kernel, &image, &cache_dir, &mut hashes).expect("Generation failed");
+        assert_eq!(fs::read_to_string(&log).expect("Missing log").lines().count(), 1);
+
+        // Inputs changed
+        fs::write(&image, "new kernel").expect("Cannot write kernel");
+        generate_cached(generator, &kernel, &image, &cache_dir, &mut hashes).expect("Generation failed");
+        assert_eq!(fs::read_to_string(&log).expect("Missing log").lines().count(), 2);
+
+        // Configuration created, then changed
+        fs::create_dir_all(&conf_dir).expect("Cannot create config dir");
+        fs::write(conf_dir.join("10-hostonly.conf"), "hostonly=yes\n").expect("Cannot write config");
+        generate_cached(generator, &kernel, &image, &cache_dir, &mut hashes).expect("Generation failed");
+        assert_eq!(fs::read_to_string(&log).expect("Missing log").lines().count(), 3);
+        fs::write(conf_dir.join("10-hostonly.conf"), "hostonly=no\n").expect("Cannot write config");
+        generate_cached(generator, &kernel, &image, &cache_dir, &mut hashes).expect("Generation failed");
+        assert_eq!(fs::read_to_string(&log).expect("Missing log").lines().count(), 4);
+
+        // Arguments changed
+        let generator = CommandGenerator::new("50-stub", &script).with_args(["--hostonly"]);
+        generate_cached(&generator, &kernel, &image, &cache_dir, &mut hashes).expect("Generation failed");
+        assert_eq!(fs::read_to_string(&log).expect("Missing log").lines().count(), 5);
+
+        // A global initrd.d entry doesn't satisfy the kernel, its own initrd does
+        let mut kernel = kernel;
+        kernel.initrd.push(AuxiliaryFile {
+            path: dir.join("initrd.d").join("10-firmware.initrd"),
+            kind: AuxiliaryKind::InitRD,
+        });
+        assert!(generator.applies_to(&kernel));
+        kernel.initrd.push(AuxiliaryFile {
+            path: dir.join("10-default.initrd"),
+            kind: AuxiliaryKind::InitRD,
+        });
+        assert!(!generator.applies_to(&kernel));
+
+        fs::remove_dir_all(&dir).expect("Cannot clean test dir");
+    }
+}
@@ -0,0 +1,150 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Persistent cache of source file hashes
+//!
+//! Hashing several large initrds on every sync quickly dominates the runtime,
+//! so we remember the blake3 hash of each source file keyed by its path, size,
+//! mtime and inode. The bootloader assets on the ESP are tracked the same way.
+//! The cache itself lives on the root filesystem, never on `$BOOT`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::file_utils::hash_file;
+
+/// A single cached hash, valid only while the file metadata matches
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedHash {
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+    inode: u64,
+    hash: String,
+}
+
+/// Source hash cache, stored within `/var/cache/blsforme`
+#[derive(Debug, Default)]
+pub struct HashCache {
+    /// Where the cache is persisted
+    path: PathBuf,
+
+    /// Known hashes, keyed by path
+    entries: BTreeMap<String, CachedHash>,
+
+    /// Paths queried during this run, anything else is pruned on save
+    used: BTreeSet<String>,
+}
+
+impl HashCache {
+    /// Default location of the cache relative to the system root
+    pub fn default_path(root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref()
+            .join("var")
+            .join("cache")
+            .join("blsforme")
+            .join("hashes.json")
+    }
+
+    /// Load the cache from disk. A missing or corrupt cache is simply empty.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let entries = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text).unwrap_or_else(|e| {
+                log::warn!("Discarding corrupt hash cache {}: {e}", path.display());
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        Self {
+            path,
+            entries,
+            used: BTreeSet::new(),
+        }
+    }
+
+    /// Return the blake3 hash (hex) of the file, only reading it when the cache is stale
+    pub fn hash(&mut self, path: impl AsRef<Path>) -> Result<String, io::Error> {
+        let path = path.as_ref();
+        let key = path.to_string_lossy().to_string();
+        let meta = fs::metadata(path)?;
+        self.used.insert(key.clone());
+
+        if let Some(cached) = self.entries.get(&key) {
+            if cached.size == meta.size()
+                && cached.mtime == meta.mtime()
+                && cached.mtime_nsec == meta.mtime_nsec()
+                && cached.inode == meta.ino()
+            {
+                log::trace!("hash cache hit: {key}");
+                return Ok(cached.hash.clone());
+            }
+        }
+
+        log::trace!("hash cache miss: {key}");
+        let hash = hash_file(path)?;
+        self.entries.insert(
+            key,
+            CachedHash {
+                size: meta.size(),
+                mtime: meta.mtime(),
+                mtime_nsec: meta.mtime_nsec(),
+                inode: meta.ino(),
+                hash: hash.clone(),
+            },
+        );
+        Ok(hash)
+    }
+
+    /// Persist the cache, dropping any entries not queried during this run
+    pub fn save(mut self) -> Result<(), io::Error> {
+        self.entries.retain(|k, _| self.used.contains(k));
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string(&self.entries)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::HashCache;
+
+    #[test]
+    fn test_cache_roundtrip() {
+        let dir = env::temp_dir().join(format!("blsforme-hash-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("Cannot create test dir");
+        let source = dir.join("10-default.initrd");
+        let stale = dir.join("stale.initrd");
+        fs::write(&source, b"initrd").expect("Cannot write test file");
+        fs::write(&stale, b"stale").expect("Cannot write test file");
+        let cache_path = HashCache::default_path(&dir);
+
+        let mut cache = HashCache::load(&cache_path);
+        let hash = cache.hash(&source).expect("Cannot hash file");
+        assert_eq!(hash, blake3::hash(b"initrd").to_hex().to_string());
+        cache.hash(&stale).expect("Cannot hash file");
+        cache.save().expect("Cannot save cache");
+
+        let mut cache = HashCache::load(&cache_path);
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.hash(&source).expect("Cannot hash file"), hash);
+        cache.save().expect("Cannot save cache");
+
+        // Unqueried entries are pruned
+        let cache = HashCache::load(&cache_path);
+        assert_eq!(cache.entries.len(), 1);
+
+        fs::remove_dir_all(&dir).expect("Cannot clean test dir");
+    }
+}
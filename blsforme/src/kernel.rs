@@ -11,8 +11,42 @@ use std::{
 
 use serde::Deserialize;
 
+use superblock::Kind;
+
 use crate::{os_release::OsRelease, Error};
 
+/// Filesystem features and the first kernel release able to mount them
+const FEATURE_RELEASES: &[(Kind, &str, (u32, u32))] = &[
+    (Kind::Ext4, "inline_data", (3, 8)),
+    (Kind::Ext4, "encrypt", (4, 1)),
+    (Kind::Ext4, "metadata_csum_seed", (4, 4)),
+    (Kind::Ext4, "ea_inode", (4, 13)),
+    (Kind::Ext4, "large_dir", (4, 13)),
+    (Kind::Ext4, "casefold", (5, 2)),
+    (Kind::Ext4, "verity", (5, 4)),
+    (Kind::Ext4, "orphan_file", (5, 15)),
+    (Kind::XFS, "sparse", (4, 2)),
+    (Kind::XFS, "rmapbt", (4, 8)),
+    (Kind::XFS, "reflink", (4, 9)),
+    (Kind::XFS, "bigtime", (5, 10)),
+    (Kind::XFS, "inobtcount", (5, 10)),
+    (Kind::XFS, "nrext64", (5, 19)),
+    (Kind::XFS, "exchange", (6, 10)),
+    (Kind::XFS, "parent", (6, 12)),
+    (Kind::XFS, "metadir", (6, 13)),
+    (Kind::Btrfs, "free_space_tree", (4, 5)),
+    (Kind::Btrfs, "compress_zstd", (4, 14)),
+    (Kind::Btrfs, "raid1c34", (5, 5)),
+    (Kind::Btrfs, "zoned", (5, 12)),
+    (Kind::Btrfs, "verity", (5, 15)),
+    (Kind::Btrfs, "block_group_tree", (6, 1)),
+    (Kind::Btrfs, "raid_stripe_tree", (6, 7)),
+    (Kind::Btrfs, "simple_quota", (6, 7)),
+    (Kind::F2FS, "casefold", (5, 4)),
+    (Kind::F2FS, "verity", (5, 4)),
+    (Kind::F2FS, "compression", (5, 6)),
+];
+
 /// Control kernel discovery mechanism
 #[derive(Debug)]
 pub enum Schema<'a> {
@@ -73,7 +107,7 @@ pub struct Kernel {
 }
 
 /// Denotes the kind of auxiliary file
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
 pub enum AuxiliaryKind {
     /// A cmdline snippet
     Cmdline,
@@ -81,6 +115,9 @@ pub enum AuxiliaryKind {
     /// An initial ramdisk
     InitRD,
 
+    /// An early microcode initrd (uncompressed cpio) for the given CPU vendor
+    Microcode(CpuVendor),
+
     /// System.map file
     SystemMap,
 
@@ -91,9 +128,78 @@ pub enum AuxiliaryKind {
     BootJSON,
 }
 
+impl Kernel {
+    /// Major and minor release, i.e. `(6, 8)` for `6.8.2-25.desktop`
+    pub fn release(&self) -> Option<(u32, u32)> {
+        let mut parts = self.version.split(['.', '-']);
+        Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+    }
+
+    /// Filter the given filesystem features down to those this kernel is too old to mount
+    pub fn unsupported_features<'f>(&self, kind: &Kind, features: &[&'f str]) -> Vec<&'f str> {
+        let Some(release) = self.release() else {
+            return vec![];
+        };
+        features
+            .iter()
+            .filter(|feature| {
+                FEATURE_RELEASES
+                    .iter()
+                    .any(|(k, f, since)| k == kind && f == *feature && release < *since)
+            })
+            .copied()
+            .collect()
+    }
+}
+
+impl AuxiliaryKind {
+    /// Classify an initrd by filename, recognising microcode initrds named
+    /// `*intel-ucode.initrd` or `*amd-ucode.initrd`
+    pub fn for_initrd(filename: &str) -> Self {
+        let stem = filename.strip_suffix(".initrd").unwrap_or(filename);
+        if stem.ends_with("intel-ucode") {
+            AuxiliaryKind::Microcode(CpuVendor::Intel)
+        } else if stem.ends_with("amd-ucode") {
+            AuxiliaryKind::Microcode(CpuVendor::AMD)
+        } else {
+            AuxiliaryKind::InitRD
+        }
+    }
+
+    /// Returns true if this is any kind of initrd
+    pub fn is_initrd(&self) -> bool {
+        matches!(self, AuxiliaryKind::InitRD | AuxiliaryKind::Microcode(_))
+    }
+}
+
+/// CPU vendor, used to select the matching microcode
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+pub enum CpuVendor {
+    /// GenuineIntel
+    Intel,
+
+    /// AuthenticAMD
+    AMD,
+}
+
+impl CpuVendor {
+    /// Determine the vendor from the contents of `/proc/cpuinfo`
+    pub fn from_cpuinfo(cpuinfo: &str) -> Option<Self> {
+        let (_, vendor) = cpuinfo
+            .lines()
+            .filter_map(|l| l.split_once(':'))
+            .find(|(k, _)| k.trim() == "vendor_id")?;
+        match vendor.trim() {
+            "GenuineIntel" => Some(CpuVendor::Intel),
+            "AuthenticAMD" => Some(CpuVendor::AMD),
+            _ => None,
+        }
+    }
+}
+
 /// An additional file required to be shipped with the kernel,
 /// such as initrds, system maps, etc.
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Debug, Clone, PartialEq, PartialOrd, Eq, Ord)]
 pub struct AuxiliaryFile {
     pub path: PathBuf,
     pub kind: AuxiliaryKind,
@@ -195,7 +301,7 @@ impl Schema<'_> {
                     }),
                     x if x == initrd_file => Some(AuxiliaryFile {
                         path: path.as_ref().into(),
-                        kind: AuxiliaryKind::InitRD,
+                        kind: AuxiliaryKind::for_initrd(x),
                     }),
                     x if x.starts_with(&initrd_file) => {
                         // Version dependent initrd
@@ -203,7 +309,7 @@ impl Schema<'_> {
                             if x.split_once(&initrd_file).is_some() {
                                 Some(AuxiliaryFile {
                                     path: path.as_ref().into(),
-                                    kind: AuxiliaryKind::InitRD,
+                                    kind: AuxiliaryKind::for_initrd(x),
                                 })
                             } else {
                                 None
@@ -218,7 +324,7 @@ impl Schema<'_> {
                             if !r.contains('.') {
                                 Some(AuxiliaryFile {
                                     path: path.as_ref().into(),
-                                    kind: AuxiliaryKind::InitRD,
+                                    kind: AuxiliaryKind::for_initrd(x),
                                 })
                             } else {
                                 None
@@ -231,7 +337,7 @@ impl Schema<'_> {
                 };
 
                 if let Some(aux_file) = aux {
-                    if matches!(aux_file.kind, AuxiliaryKind::InitRD) {
+                    if aux_file.kind.is_initrd() {
                         kernel.initrd.push(aux_file);
                     } else {
                         kernel.extras.push(aux_file);
@@ -304,7 +410,7 @@ impl Schema<'_> {
                     }),
                     _ if filename.ends_with(".initrd") => Some(AuxiliaryFile {
                         path: asset.clone(),
-                        kind: AuxiliaryKind::InitRD,
+                        kind: AuxiliaryKind::for_initrd(filename),
                     }),
                     _ if filename.ends_with(".cmdline") => Some(AuxiliaryFile {
                         path: asset.clone(),
@@ -314,7 +420,7 @@ impl Schema<'_> {
                 };
 
                 if let Some(aux_file) = aux {
-                    if matches!(aux_file.kind, AuxiliaryKind::InitRD) {
+                    if aux_file.kind.is_initrd() {
                         kernel.initrd.push(aux_file);
                     } else {
                         kernel.extras.push(aux_file);
@@ -330,15 +436,32 @@ impl Schema<'_> {
             }
         }
 
+        // Version independent initrds (`initrd.d/*.initrd`) are appended to every kernel
+        let global_initrds = all_paths
+            .iter()
+            .filter(|p| p.extension().is_some_and(|e| e == "initrd"))
+            .filter(|p| p.parent().and_then(|d| d.file_name()).is_some_and(|d| d == "initrd.d"))
+            .collect::<Vec<_>>();
+        for kernel in kernel_images.values_mut() {
+            kernel.initrd.extend(global_initrds.iter().map(|p| AuxiliaryFile {
+                path: p.to_path_buf(),
+                kind: AuxiliaryKind::for_initrd(&p.to_string_lossy()),
+            }));
+        }
+
         Ok(kernel_images.into_values().collect::<Vec<_>>())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fs;
+    use std::{fs, path::PathBuf};
+
+    use crate::os_release::OsRelease;
+
+    use superblock::Kind;
 
-    use super::BootJSON;
+    use super::{AuxiliaryKind, BootJSON, CpuVendor, Kernel, Schema};
 
     #[test]
     fn test_boot_json() {
@@ -348,4 +471,67 @@ mod tests {
         assert_eq!(boot.variant, "desktop");
         assert_eq!(boot.version, "6.8.2-25.desktop");
     }
+
+    #[test]
+    fn test_microcode() {
+        assert_eq!(
+            AuxiliaryKind::for_initrd("00-intel-ucode.initrd"),
+            AuxiliaryKind::Microcode(CpuVendor::Intel)
+        );
+        assert_eq!(
+            AuxiliaryKind::for_initrd("initrd-com.solus-project.amd-ucode"),
+            AuxiliaryKind::Microcode(CpuVendor::AMD)
+        );
+        assert_eq!(AuxiliaryKind::for_initrd("10-default.initrd"), AuxiliaryKind::InitRD);
+
+        let cpuinfo = "processor\t: 0\nvendor_id\t: AuthenticAMD\ncpu family\t: 25\n";
+        assert_eq!(CpuVendor::from_cpuinfo(cpuinfo), Some(CpuVendor::AMD));
+        assert_eq!(CpuVendor::from_cpuinfo("processor\t: 0\n"), None);
+    }
+
+    #[test]
+    fn test_unsupported_features() {
+        let kernel = Kernel {
+            version: "5.10.226-1.lts".into(),
+            image: PathBuf::from("/usr/lib/kernel/5.10.226-1.lts/vmlinuz"),
+            initrd: vec![],
+            extras: vec![],
+            variant: Some("lts".into()),
+        };
+        assert_eq!(kernel.release(), Some((5, 10)));
+
+        let features = ["has_journal", "metadata_csum_seed", "orphan_file", "casefold"];
+        assert_eq!(kernel.unsupported_features(&Kind::Ext4, &features), vec!["orphan_file"]);
+        assert!(kernel.unsupported_features(&Kind::XFS, &features).is_empty());
+    }
+
+    #[test]
+    fn test_global_initrds() {
+        let os_release = "NAME=\"Serpent OS\"\nID=serpentos"
+            .parse::<OsRelease>()
+            .expect("Invalid os-release");
+        let schema = Schema::Blsforme {
+            os_release: &os_release,
+        };
+        let paths = [
+            "/usr/lib/kernel/6.8.9-289.current/vmlinuz",
+            "/usr/lib/kernel/6.8.9-289.current/10-default.initrd",
+            "/usr/lib/kernel/initrd.d/02-ucode.initrd",
+            "/usr/lib/kernel/initrd.d/01-firmware.initrd",
+            "/usr/lib/kernel/initrd.d/README",
+        ];
+        let kernels = schema
+            .discover_system_kernels(paths.iter())
+            .expect("Failed to discover kernels");
+        assert_eq!(kernels.len(), 1);
+        let initrds = kernels[0].initrd.iter().map(|i| i.path.clone()).collect::<Vec<_>>();
+        assert_eq!(
+            initrds,
+            vec![
+                PathBuf::from("/usr/lib/kernel/6.8.9-289.current/10-default.initrd"),
+                PathBuf::from("/usr/lib/kernel/initrd.d/01-firmware.initrd"),
+                PathBuf::from("/usr/lib/kernel/initrd.d/02-ucode.initrd"),
+            ]
+        );
+    }
 }
@@ -8,15 +8,20 @@ use bootloader::systemd_boot;
 use thiserror::Error;
 
 mod kernel;
-pub use kernel::{AuxiliaryFile, AuxiliaryKind, BootJSON, Kernel, Schema};
+pub use kernel::{AuxiliaryFile, AuxiliaryKind, BootJSON, CpuVendor, Kernel, Schema};
 
 mod bootenv;
 pub use bootenv::{BootEnvironment, Firmware};
 pub mod bootloader;
+mod cmdline;
+pub use cmdline::{Cmdline, Operation, Parameter};
+pub mod generator;
+mod hash_cache;
+pub mod manifest;
 pub mod os_release;
 
 mod manager;
-pub use manager::Manager;
+pub use manager::{Manager, Verification};
 
 /// Re-export the topology APIs
 pub use topology::disk;
@@ -48,6 +53,12 @@ pub enum Error {
     #[error("failed to interact with filesystem properly")]
     InvalidFilesystem,
 
+    #[error("{0} should be FAT formatted, found {1}")]
+    InvalidBootFilesystem(PathBuf, String),
+
+    #[error("initrd generator: {0}")]
+    Generator(#[from] generator::Error),
+
     #[error("generic i/o error")]
     IO(#[from] std::io::Error),
 
@@ -5,15 +5,21 @@
 //! Boot loader management entry APIs
 
 use std::{
-    fs::{self, create_dir_all},
+    fs::{self, create_dir_all, File},
     path::{Path, PathBuf},
 };
 
 use nix::mount::{mount, umount, MsFlags};
+use superblock::{fat, Superblock};
 use topology::disk;
 
 use crate::{
-    bootloader::Bootloader, file_utils::cmdline_snippet, BootEnvironment, Configuration, Entry, Error, Kernel, Root,
+    bootloader::Bootloader,
+    entry::LocalCmdline,
+    file_utils::cmdline_snippet,
+    generator::{self, CommandGenerator, Generator},
+    hash_cache::HashCache,
+    AuxiliaryFile, AuxiliaryKind, BootEnvironment, Cmdline, Configuration, CpuVendor, Entry, Error, Kernel, Root,
     Schema,
 };
 
@@ -41,7 +47,51 @@ pub struct Manager<'a> {
 
     cmdline: Vec<String>,
 
-    system_excluded_snippets: Vec<String>,
+    /// Snippets from `/etc/kernel/cmdline.d`, replacing vendor snippets of the same name
+    local_cmdline: Vec<LocalCmdline>,
+
+    /// `/etc/kernel/cmdline`, applied after every cmdline.d snippet
+    etc_cmdline: Option<String>,
+
+    /// Initrds from `/etc/kernel/initrd.d`
+    local_initrds: Vec<AuxiliaryFile>,
+
+    /// Vendor initrds masked from `/etc/kernel/initrd.d`
+    masked_initrds: Vec<String>,
+
+    /// Restrict microcode to this CPU vendor
+    cpu_vendor: Option<CpuVendor>,
+
+    /// Local initrd generators
+    generators: Vec<Box<dyn Generator>>,
+
+    /// Integrity checks for `$BOOT` contents
+    verification: Verification,
+
+    /// Add `resume=` for hibernation from the active swap
+    resume: bool,
+
+    /// Omit `root=` when the rootfs is discoverable via the ESP's disk
+    dps: bool,
+
+    /// Whether the root partition satisfies the Discoverable Partitions Specification
+    discoverable_root: bool,
+
+    /// Root filesystem kind and feature flags, checked against each kernel
+    root_kind: Option<superblock::Kind>,
+    root_features: Vec<&'static str>,
+
+    probe: disk::probe::Probe,
+}
+
+/// Integrity checks performed when synchronising `$BOOT`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Verification {
+    /// Fully compare files on `$BOOT` rather than trusting the manifest hashes
+    pub compare: bool,
+
+    /// Read back every written file to ensure it landed intact
+    pub read_back: bool,
 }
 
 impl<'a> Manager<'a> {
@@ -52,44 +102,114 @@ impl<'a> Manager<'a> {
         let root = probe.get_rootfs_device(config.root.path())?;
         log::info!("root = {:?}", root.cmd_line());
 
+        // Image based deployments may mount `/usr` separately
+        let usr = probe.get_usr_device(config.root.path())?;
+        if let Some(usr) = usr.as_ref() {
+            log::info!("usr = {:?}", usr.cmd_line());
+        }
+
+        // Journalled filesystems record a pending replay for as long as they're mounted
+        // read-write, so the flag is only worth heeding for a read-only root
+        let root_features = match probe.get_device_superblock(&root.path) {
+            Ok(sb) => {
+                if sb.needs_recovery() == Some(true) && probe.is_mounted_read_only(config.root.path()) {
+                    log::warn!(
+                        "Root filesystem on {} needs recovery, check it with fsck before booting",
+                        root.path
+                    );
+                }
+                sb.features()
+            }
+            Err(_) => vec![],
+        };
+        let root_kind = root.kind.clone();
+
         // Right now we assume `rw` for the rootfs
-        let cmdline = [root.cmd_line(), "rw".to_string()];
-        let mut local_cmdline = vec![];
+        let mut cmdline = vec![root.cmd_line()];
+        cmdline.extend(usr.map(|u| u.cmd_line()));
+        cmdline.push("rw".to_string());
+        let etc_kernel = config.root.path().join("etc").join("kernel");
+        let etc_cmdline_d = etc_kernel.join("cmdline.d");
+        // Links into a scoped subdirectory alias that snippet, which only applies within its scope
+        let mut local_cmdline = read_local_cmdline(&etc_cmdline_d, None)
+            .into_iter()
+            .filter(|l| !links_into_scope(&etc_cmdline_d.join(&l.name), &etc_cmdline_d))
+            .collect::<Vec<_>>();
+
+        // Subdirectories scope snippets to a kernel variant or version glob
+        let mut scopes = fs::read_dir(&etc_cmdline_d)
+            .map(|i| i.filter_map(|p| p.ok()).filter(|d| d.path().is_dir()).map(|d| d.path()))
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        scopes.sort();
+        for scope in scopes {
+            let name = scope.file_name().unwrap_or_default().to_string_lossy().to_string();
+            local_cmdline.extend(read_local_cmdline(&scope, Some(&name)));
+        }
+
+        // Single file `/etc/kernel/cmdline` as used by kernel-install, applied after cmdline.d
+        let etc_cmdline = etc_kernel.join("cmdline");
+        let etc_cmdline = if etc_cmdline.is_file() && !links_into_scope(&etc_cmdline, &etc_cmdline_d) {
+            cmdline_snippet(&etc_cmdline).ok()
+        } else {
+            None
+        };
 
-        let etc_cmdline_d = config.root.path().join("etc").join("kernel").join("cmdline.d");
-        let etc_entries = fs::read_dir(&etc_cmdline_d)
+        let etc_initrd_d = config.root.path().join("etc").join("kernel").join("initrd.d");
+        let mut etc_initrds = fs::read_dir(&etc_initrd_d)
             .map(|i| {
                 i.filter_map(|p| p.ok())
-                    .filter(|d| d.path().extension().map_or(false, |e| e == "cmdline"))
+                    .filter(|d| d.path().extension().is_some_and(|e| e == "initrd"))
                     .map(|d| d.path().clone())
             })
             .into_iter()
             .flatten()
             .collect::<Vec<_>>();
-        let mut system_excludes = vec![];
+        etc_initrds.sort();
+        let mut local_initrds = vec![];
+        let mut masked_initrds = vec![];
 
-        for entry in etc_entries {
-            // For anything that's a symlink to /dev/null, we'll exclude the matching system-wide cmdline
+        for entry in etc_initrds {
+            // Likewise, a symlink to /dev/null masks the vendor initrd of the same name
             if entry.is_symlink() {
                 if let Ok(target) = entry.read_link() {
-                    if target == PathBuf::from("/dev/null") {
-                        log::trace!("excluding system-wide cmdline.d entry {:?}", entry);
-                        system_excludes.push(entry.file_name().unwrap_or_default().to_string_lossy().to_string());
+                    if target == Path::new("/dev/null") {
+                        log::trace!("masking vendor initrd.d entry {:?}", entry);
+                        masked_initrds.push(entry.file_name().unwrap_or_default().to_string_lossy().to_string());
                         continue;
                     }
                 }
             }
-            // Ensure /etc cmdline.d entries are added to the end of the generated cmdline
-            if let Ok(c) = cmdline_snippet(entry) {
-                local_cmdline.push(c);
-            }
+            local_initrds.push(AuxiliaryFile {
+                kind: AuxiliaryKind::for_initrd(&entry.to_string_lossy()),
+                path: entry,
+            });
         }
 
+        let generators = CommandGenerator::discover(config.root.path())
+            .into_iter()
+            .map(|g| Box::new(g) as Box<dyn Generator>)
+            .collect::<Vec<_>>();
+
+        // Native installs only need microcode for the running CPU, images need them all
+        let cpu_vendor = if let Root::Native(_) = config.root {
+            fs::read_to_string(config.vfs.join("proc").join("cpuinfo"))
+                .ok()
+                .and_then(|c| CpuVendor::from_cpuinfo(&c))
+        } else {
+            None
+        };
+        log::trace!("microcode vendor: {cpu_vendor:?}");
+
         // Grab parent disk, establish disk environment setup
-        let disk_parent = probe.get_device_parent(root.path);
+        let disk_parent = probe.get_device_parent(&root.path);
         let boot_env = BootEnvironment::new(&probe, disk_parent, config)?;
         log::trace!("boot env: {boot_env:?}");
 
+        let discoverable_root = boot_env.esp().is_some_and(|esp| probe.is_discoverable_root(&root, esp));
+        log::trace!("discoverable root: {discoverable_root}");
+
         let mut mounts = Mounts {
             xbootldr: if let Some(point) = boot_env.xboot_mountpoint.as_ref() {
                 Some(point.clone())
@@ -118,16 +238,26 @@ impl<'a> Manager<'a> {
             }
         }
 
-        let cmdline_joined = cmdline.iter().chain(local_cmdline.iter()).cloned().collect::<Vec<_>>();
-
         Ok(Self {
             config,
             entries: vec![],
             bootloader_assets: vec![],
             boot_env,
             mounts,
-            cmdline: cmdline_joined,
-            system_excluded_snippets: system_excludes,
+            cmdline,
+            local_cmdline,
+            etc_cmdline,
+            local_initrds,
+            masked_initrds,
+            cpu_vendor,
+            generators,
+            verification: Verification::default(),
+            resume: false,
+            dps: false,
+            discoverable_root,
+            root_kind,
+            root_features,
+            probe,
         })
     }
 
@@ -137,9 +267,39 @@ impl<'a> Manager<'a> {
     }
 
     /// Set the system kernels to use for sync operations
+    ///
+    /// Local initrds and masks from `/etc/kernel/initrd.d` are applied to each entry
     pub fn with_entries(self, entries: impl Iterator<Item = Entry<'a>>) -> Self {
         Self {
-            entries: entries.collect::<Vec<_>>(),
+            entries: entries
+                .map(|e| {
+                    e.with_local_cmdline(&self.local_cmdline)
+                        .with_etc_cmdline(self.etc_cmdline.clone())
+                        .with_local_initrds(&self.local_initrds, &self.masked_initrds)
+                        .with_cpu_vendor(self.cpu_vendor)
+                })
+                .collect::<Vec<_>>(),
+            ..self
+        }
+    }
+
+    /// Set the initrd generators, replacing any discovered in `/etc/kernel/initrd-generators.d`
+    pub fn with_initrd_generators(self, generators: Vec<Box<dyn Generator>>) -> Self {
+        Self { generators, ..self }
+    }
+
+    /// Override the CPU vendor used to select microcode initrds
+    ///
+    /// By default this is detected from `/proc/cpuinfo` for native installs,
+    /// while image mode (or `None`) includes microcode for every vendor.
+    pub fn with_cpu_vendor(self, cpu_vendor: Option<CpuVendor>) -> Self {
+        Self {
+            entries: self
+                .entries
+                .into_iter()
+                .map(|e| e.with_cpu_vendor(cpu_vendor))
+                .collect::<Vec<_>>(),
+            cpu_vendor,
             ..self
         }
     }
@@ -152,6 +312,45 @@ impl<'a> Manager<'a> {
         }
     }
 
+    /// Set the integrity checks used when writing to `$BOOT`
+    pub fn with_verification(self, verification: Verification) -> Self {
+        Self { verification, ..self }
+    }
+
+    /// Enable hibernation support, adding `resume=` (and `resume_offset=` for swap
+    /// files) for the active swap area. Only native installs are supported.
+    pub fn with_resume(self, resume: bool) -> Self {
+        Self { resume, ..self }
+    }
+
+    /// Omit `root=` when the root partition is discoverable per the Discoverable
+    /// Partitions Specification, leaving systemd-gpt-auto-generator to find it
+    pub fn with_dps(self, dps: bool) -> Self {
+        Self { dps, ..self }
+    }
+
+    /// Determine the `resume=` cmdline for the active swap, if enabled
+    fn resume_cmdline(&self) -> Option<String> {
+        if !self.resume {
+            return None;
+        }
+        if let Root::Image(_) = self.config.root {
+            log::warn!("Hibernation is not supported for image installs, skipping resume=");
+            return None;
+        }
+        match self.probe.get_resume_device() {
+            Ok(Some(device)) => Some(device.cmd_line()),
+            Ok(None) => {
+                log::warn!("No suitable swap found for hibernation, skipping resume=");
+                None
+            }
+            Err(e) => {
+                log::warn!("Failed to determine resume device: {e}");
+                None
+            }
+        }
+    }
+
     /// Mount any required partitions (ESP/XBOOTLDR)
     pub fn mount_partitions(&self) -> Result<Vec<ScopedMount>, Error> {
         let mut mounted_paths = vec![];
@@ -192,9 +391,37 @@ impl<'a> Manager<'a> {
         Ok(results)
     }
 
+    /// Ensure the partition holds a FAT filesystem, rather than failing to mount with an opaque errno
+    fn validate_vfat_partition(&self, source: &Path) -> Result<(), Error> {
+        let mut fi = File::open(source)?;
+        let Ok(fat) = fat::from_reader(&mut fi) else {
+            let found = superblock::for_reader(&mut fi)
+                .map(|sb| sb.kind().to_string())
+                .unwrap_or_else(|_| "no known filesystem".into());
+            return Err(Error::InvalidBootFilesystem(source.into(), found));
+        };
+        log::trace!(
+            "{}: {} (UUID={}, {} byte clusters)",
+            source.display(),
+            fat.variant(),
+            fat.uuid().unwrap_or_default(),
+            fat.cluster_size()
+        );
+
+        if self.boot_env.esp().is_some_and(|esp| esp == source) && fat.variant() != fat::Variant::Fat32 {
+            log::warn!(
+                "ESP {} is {}, some firmware only accepts FAT32",
+                source.display(),
+                fat.variant()
+            );
+        }
+        Ok(())
+    }
+
     /// Mount an fat filesystem
     #[inline]
     fn mount_vfat_partition(&self, source: &Path, target: &Path) -> Result<ScopedMount, Error> {
+        self.validate_vfat_partition(source)?;
         let options: Option<&str> = None;
         if !target.exists() {
             create_dir_all(target)?;
@@ -219,20 +446,100 @@ impl<'a> Manager<'a> {
                 }
             }
         }
+        self.check_root_features();
+
+        let cache_path = HashCache::default_path(self.config.root.path());
+        let mut cache = HashCache::load(&cache_path);
+
         // Firstly, get the bootloader updated.
         let bootloader = self.bootloader(schema)?;
-        bootloader.sync()?;
+        let mut changed = bootloader.sync(&mut cache, &self.verification)?;
 
         // Sync the entries
-        bootloader.sync_entries(
-            self.cmdline.iter().map(String::as_str),
-            &self.entries,
-            self.system_excluded_snippets.iter().map(String::as_str),
-        )?;
+        let entries = self.generate_initrds(&mut cache)?;
+        let automatic = self
+            .cmdline
+            .iter()
+            .cloned()
+            .chain(self.resume_cmdline())
+            .collect::<Vec<_>>();
+        let mut cmdline = Cmdline::from(automatic.join(" ").as_str());
+        if self.dps {
+            if self.discoverable_root {
+                cmdline.merge(&Cmdline::from("-root"));
+            } else {
+                log::warn!("Root partition is not discoverable, keeping root=");
+            }
+        }
+        changed += bootloader.sync_entries(&cmdline, &entries, &mut cache, &self.verification)?;
+        log::info!("Synchronised $BOOT: {changed} files changed");
+
+        // Not fatal, we'll just hash everything again next time
+        if let Err(e) = cache.save() {
+            log::warn!("Failed to save hash cache {}: {e}", cache_path.display());
+        }
 
         Ok(())
     }
 
+    /// Warn about kernels predating features of the root filesystem, which they'll fail to mount
+    fn check_root_features(&self) {
+        let Some(kind) = self.root_kind.as_ref() else {
+            return;
+        };
+        for entry in self.entries.iter() {
+            let unsupported = entry.kernel.unsupported_features(kind, &self.root_features);
+            if !unsupported.is_empty() {
+                log::warn!(
+                    "Kernel {} cannot mount the {kind} root filesystem, it predates: {}",
+                    entry.kernel.version,
+                    unsupported.join(", ")
+                );
+            }
+        }
+    }
+
+    /// Run the initrd generators for every entry requiring them
+    fn generate_initrds(&self, cache: &mut HashCache) -> Result<Vec<Entry<'a>>, Error> {
+        let cache_dir = self
+            .config
+            .root
+            .path()
+            .join("var")
+            .join("cache")
+            .join("blsforme")
+            .join("initrd");
+
+        self.entries
+            .iter()
+            .map(|entry| {
+                let image = entry.sysroot.clone().unwrap_or_default().join(&entry.kernel.image);
+                let generated = self
+                    .generators
+                    .iter()
+                    .filter(|g| g.applies_to(entry.kernel))
+                    .map(|g| generator::generate_cached(g.as_ref(), entry.kernel, &image, &cache_dir, cache))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(entry.clone().with_generated_initrds(generated))
+            })
+            .collect()
+    }
+
+    /// Take ownership of the given `$BOOT` files (loader entries or kernel trees)
+    ///
+    /// Adopted files are removed during [`Manager::sync`] once they're no longer
+    /// part of the installed set.
+    pub fn adopt(&self, schema: &Schema, paths: &[impl AsRef<Path>]) -> Result<(), Error> {
+        self.bootloader(schema)?.adopt(paths, &self.verification)?;
+        Ok(())
+    }
+
+    /// Drop ownership of the given `$BOOT` files, ensuring they're never removed
+    pub fn forget(&self, schema: &Schema, paths: &[impl AsRef<Path>]) -> Result<(), Error> {
+        self.bootloader(schema)?.forget(paths, &self.verification)?;
+        Ok(())
+    }
+
     /// factory - create bootloader instance
     fn bootloader(&'a self, schema: &'a Schema) -> Result<Bootloader<'a, 'a>, Error> {
         Ok(Bootloader::new(
@@ -262,3 +569,45 @@ impl Drop for ScopedMount {
         }
     }
 }
+
+/// Returns true if `path` resolves to a snippet within a scoped subdirectory of `cmdline_d`
+fn links_into_scope(path: &Path, cmdline_d: &Path) -> bool {
+    let (Ok(target), Ok(cmdline_d)) = (fs::canonicalize(path), fs::canonicalize(cmdline_d)) else {
+        return false;
+    };
+    target.parent().and_then(Path::parent) == Some(cmdline_d.as_path())
+}
+
+/// Read the `*.cmdline` snippets within a local cmdline.d directory
+///
+/// A symlink to `/dev/null` masks the vendor snippet of the same name.
+fn read_local_cmdline(dir: &Path, scope: Option<&str>) -> Vec<LocalCmdline> {
+    let mut paths = fs::read_dir(dir)
+        .map(|i| {
+            i.filter_map(|p| p.ok())
+                .filter(|d| d.path().extension().is_some_and(|e| e == "cmdline"))
+                .map(|d| d.path())
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    paths
+        .into_iter()
+        .filter_map(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let snippet = if path.read_link().is_ok_and(|t| t == Path::new("/dev/null")) {
+                log::trace!("masking vendor cmdline.d entry {path:?}");
+                None
+            } else {
+                Some(cmdline_snippet(&path).ok()?)
+            };
+            Some(LocalCmdline {
+                scope: scope.map(str::to_owned),
+                name,
+                snippet,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,105 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Ownership manifest for `$BOOT`
+//!
+//! blsforme records every loader entry and kernel tree it installs, so that
+//! stale cleanup is restricted to files we actually own. Anything else found
+//! on `$BOOT` (i.e. a hand written `serpentos-debug.conf`) is left untouched.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Filename of the manifest, stored within the OS specific kernel directory
+pub const FILENAME: &str = "blsforme.json";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("i/o error: {0}")]
+    IO(#[from] io::Error),
+
+    #[error("malformed manifest: {0}")]
+    Decode(#[from] serde_json::Error),
+}
+
+/// Record of everything blsforme installed to `$BOOT`
+///
+/// All paths are relative to the root of `$BOOT`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Loader entries, i.e. `loader/entries/serpentos-6.8.9-289.current.conf`
+    #[serde(default)]
+    pub entries: BTreeSet<String>,
+
+    /// Kernel trees, i.e. `EFI/serpentos/6.8.9-289.current`
+    #[serde(default)]
+    pub kernel_trees: BTreeSet<String>,
+
+    /// blake3 hashes of every installed file
+    #[serde(default)]
+    pub hashes: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Load the manifest from disk, returning `None` if it doesn't exist yet
+    pub fn load(path: impl AsRef<Path>) -> Result<Option<Self>, Error> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = fs::read_to_string(path)?;
+        Ok(Some(serde_json::from_str(&text)?))
+    }
+
+    /// Encode the manifest for writing to `$BOOT`
+    pub fn encode(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Returns true if the entry or kernel tree is owned by blsforme
+    pub fn owns(&self, path: &str) -> bool {
+        self.entries.contains(path) || self.kernel_trees.contains(path)
+    }
+
+    /// Drop ownership of the path, along with any hashes recorded within it
+    ///
+    /// Returns true if anything was tracked
+    pub fn forget(&mut self, path: &str) -> bool {
+        let tree = format!("{path}/");
+        let owned = self.entries.remove(path) | self.kernel_trees.remove(path);
+        let before = self.hashes.len();
+        self.hashes.retain(|k, _| k != path && !k.starts_with(&tree));
+        owned || before != self.hashes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Manifest;
+
+    #[test]
+    fn test_forget() {
+        let mut manifest = Manifest::default();
+        manifest.entries.insert("loader/entries/serpentos-6.8.9.conf".into());
+        manifest.kernel_trees.insert("EFI/serpentos/6.8.9".into());
+        manifest
+            .hashes
+            .insert("EFI/serpentos/6.8.9/vmlinuz".into(), "abc".into());
+        manifest
+            .hashes
+            .insert("EFI/serpentos/6.8.90/vmlinuz".into(), "def".into());
+
+        assert!(manifest.owns("EFI/serpentos/6.8.9"));
+        assert!(manifest.forget("EFI/serpentos/6.8.9"));
+        assert!(!manifest.owns("EFI/serpentos/6.8.9"));
+        assert_eq!(manifest.hashes.len(), 1);
+        assert!(!manifest.forget("loader/entries/serpentos-debug.conf"));
+    }
+}
@@ -0,0 +1,131 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! bcachefs superblock handling
+//!
+//! Every member device carries a superblock with the same external (user) UUID,
+//! which is what `root=UUID=` must refer to.
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+use uuid::Uuid;
+
+const LABEL_SIZE: usize = 32;
+
+/// Leading portion of struct bch_sb (as seen in the kernel), up to the superblock layout
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Bcachefs {
+    csum_lo: Le64,
+    csum_hi: Le64,
+    version: Le16,
+    version_min: Le16,
+    pad: [Le16; 2],
+    magic: [u8; 16],
+    uuid: [u8; 16],
+    user_uuid: [u8; 16],
+    label: [u8; LABEL_SIZE],
+    offset: Le64,
+    seq: Le64,
+    block_size: Le16,
+    dev_idx: u8,
+    nr_devices: u8,
+    u64s: Le32,
+    time_base_lo: Le64,
+    time_base_hi: Le32,
+    time_precision: Le32,
+    flags: [Le64; 7],
+    write_time: Le64,
+    features: [Le64; 2],
+    compat: [Le64; 2],
+}
+
+// Superblock lives at sector 8
+const START_POSITION: u64 = 4096;
+
+/// Shared with bcache, which bcachefs still accepts for older filesystems
+const BCACHE_MAGIC: [u8; 16] = [
+    0xc6, 0x85, 0x73, 0xf6, 0x4e, 0x1a, 0x45, 0xca, 0x82, 0x65, 0xf5, 0x7f, 0x48, 0xba, 0x6d, 0x81,
+];
+const BCHFS_MAGIC: [u8; 16] = [
+    0xc6, 0x85, 0x73, 0xf6, 0x66, 0xce, 0x90, 0xa9, 0xd9, 0x6a, 0x60, 0xcf, 0x80, 0x3d, 0xf7, 0xef,
+];
+
+/// Attempt to decode the Superblock from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Bcachefs, Error> {
+    decode::skip(reader, START_POSITION)?;
+    let data: Bcachefs = decode::read(reader)?;
+
+    if data.magic != BCACHE_MAGIC && data.magic != BCHFS_MAGIC {
+        return Err(Error::InvalidMagic);
+    }
+
+    // Plain bcache backing devices share the magic but record no members
+    if data.nr_devices == 0 {
+        return Err(Error::InvalidMagic);
+    }
+
+    log::trace!(
+        "valid magic field: UUID={} [volume label: \"{}\"]",
+        data.uuid()?,
+        data.label().unwrap_or_else(|_| "[invalid utf8]".into())
+    );
+    Ok(data)
+}
+
+impl Bcachefs {
+    /// Number of member devices in the filesystem
+    pub fn nr_devices(&self) -> u8 {
+        self.nr_devices
+    }
+
+    /// Index of this device within the filesystem
+    pub fn dev_idx(&self) -> u8 {
+        self.dev_idx
+    }
+
+    /// Internal UUID, distinct from the external UUID used for mounting
+    pub fn internal_uuid(&self) -> String {
+        Uuid::from_bytes(self.uuid).hyphenated().to_string()
+    }
+}
+
+impl Superblock for Bcachefs {
+    /// Return the external UUID, as used by `root=UUID=`
+    fn uuid(&self) -> Result<String, Error> {
+        Ok(Uuid::from_bytes(self.user_uuid).hyphenated().to_string())
+    }
+
+    /// Return the volume label as valid utf8
+    fn label(&self) -> Result<String, Error> {
+        Ok(std::str::from_utf8(&self.label)?.trim_end_matches('\0').to_owned())
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Bcachefs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{bcachefs::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/bcachefs.img.zst").expect("cannot open bcachefs img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.label().unwrap(), "blsforme testing");
+        assert_eq!(sb.uuid().unwrap(), "a7d3f1e2-9b4c-4d8e-8f6a-2c1b0e9d8f7a");
+        assert_eq!(sb.internal_uuid(), "1e5c7b9d-3f2a-4b6c-8d0e-9a7f5c3b1d2e");
+        assert_eq!(sb.nr_devices(), 1);
+        assert_eq!(sb.dev_idx(), 0);
+    }
+}
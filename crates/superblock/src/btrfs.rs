@@ -3,33 +3,109 @@
 // SPDX-License-Identifier: MPL-2.0
 
 //! BTRFS superblock handling
-//! TODO: Add full representation of the superblock to allow us to
-//! discover volumes and the root label.
 
-use crate::{Error, Kind, Superblock};
-use log;
-use std::{
-    io::{self, Read},
-    slice,
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
 };
+use log;
+use std::io::Read;
 use uuid::Uuid;
 
+const LABEL_SIZE: usize = 256;
+const SYSTEM_CHUNK_ARRAY_SIZE: usize = 2048;
+const NUM_BACKUP_ROOTS: usize = 4;
+
+/// struct btrfs_dev_item, describing the device this superblock was read from
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct DevItem {
+    devid: Le64,
+    total_bytes: Le64,
+    bytes_used: Le64,
+    io_align: Le32,
+    io_width: Le32,
+    sector_size: Le32,
+    type_: Le64,
+    generation: Le64,
+    start_offset: Le64,
+    dev_group: Le32,
+    seek_speed: u8,
+    bandwidth: u8,
+    uuid: [u8; 16],
+    fsid: [u8; 16],
+}
+
+/// struct btrfs_root_backup
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct RootBackup {
+    tree_root: Le64,
+    tree_root_gen: Le64,
+    chunk_root: Le64,
+    chunk_root_gen: Le64,
+    extent_root: Le64,
+    extent_root_gen: Le64,
+    fs_root: Le64,
+    fs_root_gen: Le64,
+    dev_root: Le64,
+    dev_root_gen: Le64,
+    csum_root: Le64,
+    csum_root_gen: Le64,
+    total_bytes: Le64,
+    bytes_used: Le64,
+    num_devices: Le64,
+    unused_64: [Le64; 4],
+    tree_root_level: u8,
+    chunk_root_level: u8,
+    extent_root_level: u8,
+    fs_root_level: u8,
+    dev_root_level: u8,
+    csum_root_level: u8,
+    unused_8: [u8; 10],
+}
+
 /// BTRFS superblock definition (as seen in the kernel)
-/// This is a PARTIAL representation that matches only the
-/// first 72 bytes, verifies the magic, and permits extraction
-/// of the UUID
-#[derive(Debug)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
 #[repr(C)]
 pub struct Btrfs {
     csum: [u8; 32],
     fsid: [u8; 16],
-    bytenr: u64,
-    flags: u64,
-    magic: u64,
-    generation: u64,
-    root: u64,
-    chunk_root: u64,
-    log_root: u64,
+    bytenr: Le64,
+    flags: Le64,
+    magic: Le64,
+    generation: Le64,
+    root: Le64,
+    chunk_root: Le64,
+    log_root: Le64,
+    log_root_transid: Le64,
+    total_bytes: Le64,
+    bytes_used: Le64,
+    root_dir_objectid: Le64,
+    num_devices: Le64,
+    sectorsize: Le32,
+    nodesize: Le32,
+    leafsize: Le32,
+    stripesize: Le32,
+    sys_chunk_array_size: Le32,
+    chunk_root_generation: Le64,
+    compat_flags: Le64,
+    compat_ro_flags: Le64,
+    incompat_flags: Le64,
+    csum_type: Le16,
+    root_level: u8,
+    chunk_root_level: u8,
+    log_root_level: u8,
+    dev_item: DevItem,
+    label: [u8; LABEL_SIZE],
+    cache_generation: Le64,
+    uuid_tree_generation: Le64,
+    metadata_uuid: [u8; 16],
+    nr_global_roots: Le64,
+    reserved: [Le64; 27],
+    sys_chunk_array: [u8; SYSTEM_CHUNK_ARRAY_SIZE],
+    super_roots: [RootBackup; NUM_BACKUP_ROOTS],
+    padding: [u8; 565],
 }
 
 // Superblock starts at 65536 for btrfs.
@@ -38,37 +114,137 @@ const START_POSITION: u64 = 0x10000;
 // "_BHRfS_M"
 const MAGIC: u64 = 0x4D5F53665248425F;
 
+const _: () = assert!(std::mem::size_of::<Btrfs>() == 4096);
+
+/// Checksum covers everything after the checksum field itself
+const CSUM_SIZE: usize = 32;
+const CSUM_TYPE_CRC32C: u16 = 0;
+
+/// Feature names as used by `btrfs inspect-internal dump-super`
+const FEATURES_COMPAT_RO: &[(u64, &str)] = &[
+    (0x1, "free_space_tree"),
+    (0x2, "free_space_tree_valid"),
+    (0x4, "verity"),
+    (0x8, "block_group_tree"),
+];
+const FEATURES_INCOMPAT: &[(u64, &str)] = &[
+    (0x1, "mixed_backref"),
+    (0x2, "default_subvol"),
+    (0x4, "mixed_groups"),
+    (0x8, "compress_lzo"),
+    (0x10, "compress_zstd"),
+    (0x20, "big_metadata"),
+    (0x40, "extended_iref"),
+    (0x80, "raid56"),
+    (0x100, "skinny_metadata"),
+    (0x200, "no_holes"),
+    (0x400, "metadata_uuid"),
+    (0x800, "raid1c34"),
+    (0x1000, "zoned"),
+    (0x2000, "extent_tree_v2"),
+    (0x4000, "raid_stripe_tree"),
+    (0x10000, "simple_quota"),
+];
+
 /// Attempt to decode the Superblock from the given read stream
 pub fn from_reader<R: Read>(reader: &mut R) -> Result<Btrfs, Error> {
-    const SIZE: usize = std::mem::size_of::<Btrfs>();
-    let mut data: Btrfs = unsafe { std::mem::zeroed() };
-    let data_sliced = unsafe { slice::from_raw_parts_mut(&mut data as *mut _ as *mut u8, SIZE) };
+    decode::skip(reader, START_POSITION)?;
+    let data: Btrfs = decode::read(reader)?;
 
-    // Drop unwanted bytes (Seek not possible with zstd streamed inputs)
-    io::copy(&mut reader.by_ref().take(START_POSITION), &mut io::sink())?;
-    reader.read_exact(data_sliced)?;
-
-    if data.magic != MAGIC {
+    if data.magic.get() != MAGIC {
         Err(Error::InvalidMagic)
+    } else if !data.is_checksum_valid() {
+        Err(Error::InvalidChecksum)
     } else {
-        log::trace!("valid magic field: UUID={}", data.uuid()?);
+        log::trace!(
+            "valid magic field: UUID={} [volume label: \"{}\"]",
+            data.uuid()?,
+            data.label().unwrap_or_else(|_| "[invalid utf8]".into())
+        );
         Ok(data)
     }
 }
 
+impl Btrfs {
+    /// Whether the superblock checksum is one we can verify (crc32c)
+    pub fn has_checksum(&self) -> bool {
+        self.csum_type.get() == CSUM_TYPE_CRC32C
+    }
+
+    /// Only crc32c is verified, the other (stronger) checksum types are trusted
+    fn is_checksum_valid(&self) -> bool {
+        if !self.has_checksum() {
+            return true;
+        }
+        let csum = !decode::crc32c(!0, &self.as_bytes()[CSUM_SIZE..]);
+        self.csum[..4] == csum.to_le_bytes()
+    }
+
+    /// Number of devices making up this filesystem
+    pub fn num_devices(&self) -> u64 {
+        self.num_devices.get()
+    }
+
+    /// Total size of the filesystem in bytes, across all devices
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes.get()
+    }
+
+    /// Description of the device this superblock was read from
+    pub fn dev_item(&self) -> DevItem {
+        self.dev_item
+    }
+}
+
+impl DevItem {
+    /// Device ID within the filesystem, starting at 1
+    pub fn devid(&self) -> u64 {
+        self.devid.get()
+    }
+
+    /// Size of this device in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes.get()
+    }
+
+    /// Per-device UUID
+    pub fn uuid(&self) -> String {
+        Uuid::from_bytes(self.uuid).hyphenated().to_string()
+    }
+}
+
 impl Superblock for Btrfs {
     /// Return the encoded UUID for this superblock
     fn uuid(&self) -> Result<String, Error> {
         Ok(Uuid::from_bytes(self.fsid).hyphenated().to_string())
     }
 
+    fn size(&self) -> Option<u64> {
+        Some(self.total_bytes.get())
+    }
+
+    fn block_size(&self) -> Option<u32> {
+        Some(self.sectorsize.get())
+    }
+
+    fn features(&self) -> Vec<&'static str> {
+        decode::flag_names(self.compat_ro_flags.get(), FEATURES_COMPAT_RO)
+            .chain(decode::flag_names(self.incompat_flags.get(), FEATURES_INCOMPAT))
+            .collect()
+    }
+
+    /// A log tree left to replay, following a crash after fsync
+    fn needs_recovery(&self) -> Option<bool> {
+        Some(self.log_root.get() != 0)
+    }
+
     fn kind(&self) -> Kind {
         super::Kind::Btrfs
     }
 
-    /// We don't yet support labels here.
+    /// Return the volume label as valid utf8
     fn label(&self) -> Result<String, Error> {
-        Err(Error::UnsupportedFeature)
+        Ok(std::str::from_utf8(&self.label)?.trim_end_matches('\0').to_owned())
     }
 }
 
@@ -84,5 +260,9 @@ mod tests {
         let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
         let sb = from_reader(&mut stream).expect("Cannot parse superblock");
         assert_eq!(sb.uuid().unwrap(), "829d6a03-96a5-4749-9ea2-dbb6e59368b2");
+        assert_eq!(sb.label().unwrap(), "blsforme testing");
+        assert_eq!(sb.num_devices(), 1);
+        assert_eq!(sb.dev_item().devid(), 1);
+        assert_eq!(std::mem::size_of::<crate::btrfs::Btrfs>(), 4096);
     }
 }
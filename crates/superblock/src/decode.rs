@@ -0,0 +1,97 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Safe decoding of on-disk structures
+//!
+//! Structures are declared purely in terms of byte arrays and explicit endian
+//! integer types (i.e. [`Le32`]), so they have neither alignment nor padding and
+//! can be read straight from the stream without any `unsafe`. Integers must be
+//! accessed via `.get()`, which always yields the native value.
+
+use std::io::{self, Read};
+
+pub(crate) use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
+
+use crate::Error;
+
+pub(crate) type Le16 = zerocopy::little_endian::U16;
+pub(crate) type Le32 = zerocopy::little_endian::U32;
+pub(crate) type Le64 = zerocopy::little_endian::U64;
+pub(crate) type LeI32 = zerocopy::little_endian::I32;
+pub(crate) type Be16 = zerocopy::big_endian::U16;
+pub(crate) type Be32 = zerocopy::big_endian::U32;
+pub(crate) type Be64 = zerocopy::big_endian::U64;
+pub(crate) type BeI64 = zerocopy::big_endian::I64;
+
+/// Read a structure from the current position of the stream
+pub(crate) fn read<T: FromBytes, R: Read>(reader: &mut R) -> Result<T, Error> {
+    Ok(T::read_from_io(reader)?)
+}
+
+/// Drop unwanted bytes (Seek not possible with zstd streamed inputs)
+pub(crate) fn skip<R: Read>(reader: &mut R, len: u64) -> Result<(), Error> {
+    let skipped = io::copy(&mut reader.by_ref().take(len), &mut io::sink())?;
+    if skipped != len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(())
+}
+
+/// Bounds-checked read of a structure from a raw byte region
+pub(crate) fn read_at<T: FromBytes>(data: &[u8], offset: usize) -> Option<T> {
+    T::read_from_prefix(data.get(offset..)?).ok().map(|(value, _)| value)
+}
+
+/// Bounds-checked little endian `u16` from a raw byte region
+pub(crate) fn le16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+/// Bounds-checked little endian `u32` from a raw byte region
+pub(crate) fn le32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+/// Names of the flags set in `value`, per the `(flag, name)` table
+pub(crate) fn flag_names(value: u64, table: &'static [(u64, &'static str)]) -> impl Iterator<Item = &'static str> {
+    table
+        .iter()
+        .filter(move |(flag, _)| value & flag != 0)
+        .map(|(_, name)| *name)
+}
+
+/// Reflected CRC32 without the final inversion, as the kernel's `crc32_le`
+fn crc32_reflected(poly: u32, seed: u32, data: &[u8]) -> u32 {
+    data.iter().fold(seed, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            (crc >> 1) ^ (poly & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// CRC32C (Castagnoli), as the kernel's `crc32c(seed, ...)`
+pub(crate) fn crc32c(seed: u32, data: &[u8]) -> u32 {
+    crc32_reflected(0x82f63b78, seed, data)
+}
+
+/// CRC32 (IEEE), as the kernel's `crc32_le(seed, ...)`
+pub(crate) fn crc32(seed: u32, data: &[u8]) -> u32 {
+    crc32_reflected(0xedb88320, seed, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, crc32c};
+
+    #[test]
+    fn test_crc() {
+        // Standard check values, which apply the final inversion
+        assert_eq!(!crc32c(!0, b"123456789"), 0xe3069283);
+        assert_eq!(!crc32(!0, b"123456789"), 0xcbf43926);
+    }
+}
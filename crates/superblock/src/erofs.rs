@@ -0,0 +1,125 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! EROFS superblock handling
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+use uuid::Uuid;
+
+/// struct erofs_super_block (as seen in the kernel), all integers are little endian
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Erofs {
+    magic: Le32,
+    checksum: Le32,
+    feature_compat: Le32,
+    blkszbits: u8,
+    sb_extslots: u8,
+    root_nid: Le16,
+    inos: Le64,
+    build_time: Le64,
+    build_time_nsec: Le32,
+    blocks: Le32,
+    meta_blkaddr: Le32,
+    xattr_blkaddr: Le32,
+    uuid: [u8; 16],
+    volume_name: [u8; 16],
+    feature_incompat: Le32,
+    available_compr_algs: Le16,
+    extra_devices: Le16,
+    devt_slotoff: Le16,
+    dirblkbits: u8,
+    xattr_prefix_count: u8,
+    xattr_prefix_start: Le32,
+    packed_nid: Le64,
+    xattr_filter_reserved: u8,
+    reserved: [u8; 23],
+}
+
+const MAGIC: u32 = 0xe0f5e1e2;
+const START_POSITION: u64 = 1024;
+
+const _: () = assert!(std::mem::size_of::<Erofs>() == 128);
+
+/// Attempt to decode the Superblock from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Erofs, Error> {
+    decode::skip(reader, START_POSITION)?;
+    let data: Erofs = decode::read(reader)?;
+
+    if data.magic.get() != MAGIC {
+        Err(Error::InvalidMagic)
+    } else {
+        log::trace!(
+            "valid magic field: UUID={} [volume label: \"{}\"]",
+            data.uuid()?,
+            data.label().unwrap_or_else(|_| "[invalid utf8]".into())
+        );
+        Ok(data)
+    }
+}
+
+impl Erofs {
+    /// Block size in bytes
+    pub fn block_size(&self) -> u32 {
+        1u32.checked_shl(self.blkszbits as u32).unwrap_or(0)
+    }
+
+    /// Size of the filesystem in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.blocks.get() as u64 * self.block_size() as u64
+    }
+
+    /// Build time (seconds since the epoch)
+    pub fn build_time(&self) -> u64 {
+        self.build_time.get()
+    }
+}
+
+impl Superblock for Erofs {
+    /// Return the encoded UUID for this superblock
+    fn uuid(&self) -> Result<String, Error> {
+        Ok(Uuid::from_bytes(self.uuid).hyphenated().to_string())
+    }
+
+    /// Return the volume label as valid utf8
+    fn label(&self) -> Result<String, Error> {
+        Ok(std::str::from_utf8(&self.volume_name)?
+            .trim_end_matches('\0')
+            .to_owned())
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.total_bytes())
+    }
+
+    fn block_size(&self) -> Option<u32> {
+        Some(Erofs::block_size(self))
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::EROFS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{erofs::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/erofs.img.zst").expect("cannot open erofs img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.label().unwrap(), "blsforme testing");
+        assert_eq!(sb.uuid().unwrap(), "f1e2d3c4-b5a6-4978-8a9b-0c1d2e3f4a5b");
+        assert_eq!(sb.block_size(), 4096);
+    }
+}
@@ -0,0 +1,194 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! exFAT boot sector handling
+//!
+//! The volume label isn't part of the boot sector, it is a directory entry
+//! within the first cluster of the root directory.
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+
+/// Main boot sector, all integers are little endian
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct BootSector {
+    jump: [u8; 3],
+    fs_name: [u8; 8],
+    must_be_zero: [u8; 53],
+    partition_offset: Le64,
+    volume_length: Le64,
+    fat_offset: Le32,
+    fat_length: Le32,
+    cluster_heap_offset: Le32,
+    cluster_count: Le32,
+    first_cluster_of_root: Le32,
+    volume_serial: Le32,
+    fs_revision: Le16,
+    volume_flags: Le16,
+    bytes_per_sector_shift: u8,
+    sectors_per_cluster_shift: u8,
+    number_of_fats: u8,
+    drive_select: u8,
+    percent_in_use: u8,
+    reserved: [u8; 7],
+    boot_code: [u8; 390],
+    signature: [u8; 2],
+}
+
+const MAGIC: &[u8; 8] = b"EXFAT   ";
+
+/// Directory entry types
+const ENTRY_END: u8 = 0x00;
+const ENTRY_VOLUME_LABEL: u8 = 0x83;
+const ENTRY_SIZE: usize = 32;
+
+/// Label is at most 11 UTF-16 characters
+const LABEL_LENGTH: usize = 11;
+
+/// An exFAT filesystem, with the label from the root directory (if found)
+#[derive(Debug)]
+pub struct ExFat {
+    boot_sector: BootSector,
+    label: Option<String>,
+}
+
+/// Attempt to decode the boot sector from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<ExFat, Error> {
+    let data: BootSector = decode::read(reader)?;
+
+    if &data.fs_name != MAGIC || data.signature != [0x55, 0xaa] || data.must_be_zero.iter().any(|b| *b != 0) {
+        return Err(Error::InvalidMagic);
+    }
+
+    // Sectors are 512 to 4096 bytes, clusters at most 32MiB
+    let bytes_per_sector_shift = data.bytes_per_sector_shift;
+    let sectors_per_cluster_shift = data.sectors_per_cluster_shift;
+    if !(9..=12).contains(&bytes_per_sector_shift) || bytes_per_sector_shift + sectors_per_cluster_shift > 25 {
+        return Err(Error::InvalidMagic);
+    }
+
+    let mut exfat = ExFat {
+        boot_sector: data,
+        label: None,
+    };
+    exfat.label = exfat.read_label(reader).ok().flatten();
+
+    log::trace!(
+        "valid magic field: UUID={} [volume label: \"{}\"]",
+        exfat.uuid()?,
+        exfat.label().unwrap_or_else(|_| "[invalid utf16]".into())
+    );
+    Ok(exfat)
+}
+
+impl ExFat {
+    /// Sector size in bytes
+    pub fn sector_size(&self) -> u32 {
+        1 << self.boot_sector.bytes_per_sector_shift
+    }
+
+    /// Cluster size in bytes
+    pub fn cluster_size(&self) -> u32 {
+        self.sector_size() << self.boot_sector.sectors_per_cluster_shift
+    }
+
+    /// Size of the filesystem in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.boot_sector.volume_length.get() * self.sector_size() as u64
+    }
+
+    /// Number of clusters in the cluster heap
+    pub fn cluster_count(&self) -> u32 {
+        self.boot_sector.cluster_count.get()
+    }
+
+    /// Filesystem revision as `(major, minor)`
+    pub fn revision(&self) -> (u8, u8) {
+        let revision = self.boot_sector.fs_revision.get();
+        ((revision >> 8) as u8, revision as u8)
+    }
+
+    /// Find the volume label entry, with the reader positioned just after the boot sector
+    fn read_label<R: Read>(&self, reader: &mut R) -> Result<Option<String>, Error> {
+        let bs = &self.boot_sector;
+        let root_cluster = bs.first_cluster_of_root.get();
+        if root_cluster < 2 {
+            return Ok(None);
+        }
+
+        // Only the first cluster of the root directory is considered
+        let root_sector =
+            bs.cluster_heap_offset.get() as u64 + ((root_cluster as u64 - 2) << bs.sectors_per_cluster_shift);
+        let consumed = std::mem::size_of::<BootSector>() as u64;
+        let Some(skip) = (root_sector << bs.bytes_per_sector_shift).checked_sub(consumed) else {
+            return Ok(None);
+        };
+        decode::skip(reader, skip)?;
+        let mut cluster = vec![0u8; self.cluster_size() as usize];
+        reader.read_exact(&mut cluster)?;
+
+        for entry in cluster.chunks_exact(ENTRY_SIZE) {
+            match entry[0] {
+                ENTRY_END => break,
+                ENTRY_VOLUME_LABEL => {
+                    let length = (entry[1] as usize).min(LABEL_LENGTH);
+                    let chars = (0..length)
+                        .filter_map(|i| decode::le16(entry, 2 + i * 2))
+                        .collect::<Vec<_>>();
+                    return Ok(Some(String::from_utf16(&chars)?));
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Superblock for ExFat {
+    /// Return the volume serial in the `XXXX-XXXX` form used by `UUID=`
+    fn uuid(&self) -> Result<String, Error> {
+        let serial = self.boot_sector.volume_serial.get();
+        Ok(format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff))
+    }
+
+    /// Return the volume label from the root directory
+    fn label(&self) -> Result<String, Error> {
+        Ok(self.label.clone().unwrap_or_default())
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.total_bytes())
+    }
+
+    fn block_size(&self) -> Option<u32> {
+        Some(self.cluster_size())
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::ExFat
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{exfat::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/exfat.img.zst").expect("cannot open exfat img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.uuid().unwrap(), "3C1D-9E27");
+        assert_eq!(sb.label().unwrap(), "blsforme");
+        assert_eq!(sb.cluster_size(), 4096);
+        assert_eq!(sb.revision(), (1, 0));
+    }
+}
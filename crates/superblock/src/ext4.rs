@@ -4,119 +4,175 @@
 
 //! EXT4 superblock handling
 
-use crate::{Error, Kind, Superblock};
-use log;
-use std::{
-    io::{self, Read},
-    slice,
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
 };
+use log;
+use std::io::Read;
 use uuid::Uuid;
 
 /// EXT4 Superblock definition (as seen in the kernel)
-#[derive(Debug)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
 #[repr(C)]
 pub struct Ext4 {
-    inodes_count: u32,
-    block_counts_lo: u32,
-    r_blocks_count_lo: u32,
-    free_blocks_count_lo: u32,
-    free_inodes_count: u32,
-    first_data_block: u32,
-    log_block_size: u32,
-    log_cluster_size: u32,
-    blocks_per_group: u32,
-    clusters_per_group: u32,
-    inodes_per_group: u32,
-    m_time: u32,
-    w_time: u32,
-    mnt_count: u16,
-    max_mnt_count: u16,
-    magic: u16,
-    state: u16,
-    errors: u16,
-    minor_rev_level: u16,
-    lastcheck: u32,
-    checkinterval: u32,
-    creator_os: u32,
-    rev_level: u32,
-    def_resuid: u16,
-    def_resgid: u16,
-    first_ino: u32,
-    inode_size: u16,
-    block_group_nr: u16,
-    feature_compat: u32,
-    feature_incompat: u32,
-    feature_ro_compat: u32,
+    inodes_count: Le32,
+    block_counts_lo: Le32,
+    r_blocks_count_lo: Le32,
+    free_blocks_count_lo: Le32,
+    free_inodes_count: Le32,
+    first_data_block: Le32,
+    log_block_size: Le32,
+    log_cluster_size: Le32,
+    blocks_per_group: Le32,
+    clusters_per_group: Le32,
+    inodes_per_group: Le32,
+    m_time: Le32,
+    w_time: Le32,
+    mnt_count: Le16,
+    max_mnt_count: Le16,
+    magic: Le16,
+    state: Le16,
+    errors: Le16,
+    minor_rev_level: Le16,
+    lastcheck: Le32,
+    checkinterval: Le32,
+    creator_os: Le32,
+    rev_level: Le32,
+    def_resuid: Le16,
+    def_resgid: Le16,
+    first_ino: Le32,
+    inode_size: Le16,
+    block_group_nr: Le16,
+    feature_compat: Le32,
+    feature_incompat: Le32,
+    feature_ro_compat: Le32,
     uuid: [u8; 16],
     volume_name: [u8; 16],
     last_mounted: [u8; 64],
-    algorithm_usage_bitmap: u32,
+    algorithm_usage_bitmap: Le32,
     prealloc_blocks: u8,
     prealloc_dir_blocks: u8,
-    reserved_gdt_blocks: u16,
+    reserved_gdt_blocks: Le16,
     journal_uuid: [u8; 16],
-    journal_inum: u32,
-    journal_dev: u32,
-    last_orphan: u32,
-    hash_seed: [u32; 4],
+    journal_inum: Le32,
+    journal_dev: Le32,
+    last_orphan: Le32,
+    hash_seed: [Le32; 4],
     def_hash_version: u8,
     jnl_backup_type: u8,
-    desc_size: u16,
-    default_mount_opts: u32,
-    first_meta_bg: u32,
-    mkfs_time: u32,
-    jnl_blocks: [u32; 17],
-    blocks_count_hi: u32,
-    free_blocks_count_hi: u32,
-    min_extra_isize: u16,
-    want_extra_isize: u16,
-    flags: u32,
-    raid_stride: u16,
-    mmp_update_interval: u16,
-    mmp_block: u64,
-    raid_stripe_width: u32,
+    desc_size: Le16,
+    default_mount_opts: Le32,
+    first_meta_bg: Le32,
+    mkfs_time: Le32,
+    jnl_blocks: [Le32; 17],
+    blocks_count_hi: Le32,
+    r_blocks_count_hi: Le32,
+    free_blocks_count_hi: Le32,
+    min_extra_isize: Le16,
+    want_extra_isize: Le16,
+    flags: Le32,
+    raid_stride: Le16,
+    mmp_update_interval: Le16,
+    mmp_block: Le64,
+    raid_stripe_width: Le32,
     log_groups_per_flex: u8,
     checksum_type: u8,
-    reserved_pad: u16,
-    kbytes_written: u64,
-    snapshot_inum: u32,
-    snapshot_id: u32,
-    snapshot_r_blocks_count: u64,
-    snapshot_list: u32,
-    error_count: u32,
-    first_error_time: u32,
-    first_error_inod: u32,
-    first_error_block: u64,
+    reserved_pad: Le16,
+    kbytes_written: Le64,
+    snapshot_inum: Le32,
+    snapshot_id: Le32,
+    snapshot_r_blocks_count: Le64,
+    snapshot_list: Le32,
+    error_count: Le32,
+    first_error_time: Le32,
+    first_error_inod: Le32,
+    first_error_block: Le64,
     first_error_func: [u8; 32],
-    first_error_line: u32,
-    last_error_time: u32,
-    last_error_inod: u32,
-    last_error_line: u32,
-    last_error_block: u64,
+    first_error_line: Le32,
+    last_error_time: Le32,
+    last_error_inod: Le32,
+    last_error_line: Le32,
+    last_error_block: Le64,
     last_error_func: [u8; 32],
     mount_opts: [u8; 64],
-    usr_quota_inum: u32,
-    grp_quota_inum: u32,
-    overhead_clusters: u32,
-    reserved: [u32; 108],
-    checksum: u32,
+    usr_quota_inum: Le32,
+    grp_quota_inum: Le32,
+    overhead_clusters: Le32,
+    reserved: [Le32; 108],
+    checksum: Le32,
 }
 
+const _: () = assert!(std::mem::size_of::<Ext4>() == 1024);
+
 const MAGIC: u16 = 0xEF53;
 const START_POSITION: u64 = 1024;
 
+/// `RO_COMPAT_METADATA_CSUM`, the superblock carries a crc32c
+const FEATURE_RO_COMPAT_METADATA_CSUM: u32 = 0x400;
+const CHECKSUM_TYPE_CRC32C: u8 = 1;
+
+const FEATURE_INCOMPAT_RECOVER: u32 = 0x4;
+const FEATURE_INCOMPAT_64BIT: u32 = 0x80;
+
+/// `s_state`, errors were detected and fsck is required
+const STATE_ERROR_FS: u16 = 0x2;
+
+/// Feature names as used by `tune2fs`
+const FEATURES_COMPAT: &[(u64, &str)] = &[
+    (0x1, "dir_prealloc"),
+    (0x2, "imagic_inodes"),
+    (0x4, "has_journal"),
+    (0x8, "ext_attr"),
+    (0x10, "resize_inode"),
+    (0x20, "dir_index"),
+    (0x200, "sparse_super2"),
+    (0x400, "fast_commit"),
+    (0x1000, "orphan_file"),
+];
+const FEATURES_INCOMPAT: &[(u64, &str)] = &[
+    (0x1, "compression"),
+    (0x2, "filetype"),
+    (0x4, "needs_recovery"),
+    (0x8, "journal_dev"),
+    (0x10, "meta_bg"),
+    (0x40, "extent"),
+    (0x80, "64bit"),
+    (0x100, "mmp"),
+    (0x200, "flex_bg"),
+    (0x400, "ea_inode"),
+    (0x1000, "dirdata"),
+    (0x2000, "metadata_csum_seed"),
+    (0x4000, "large_dir"),
+    (0x8000, "inline_data"),
+    (0x10000, "encrypt"),
+    (0x20000, "casefold"),
+];
+const FEATURES_RO_COMPAT: &[(u64, &str)] = &[
+    (0x1, "sparse_super"),
+    (0x2, "large_file"),
+    (0x8, "huge_file"),
+    (0x10, "uninit_bg"),
+    (0x20, "dir_nlink"),
+    (0x40, "extra_isize"),
+    (0x100, "quota"),
+    (0x200, "bigalloc"),
+    (0x400, "metadata_csum"),
+    (0x1000, "read-only"),
+    (0x2000, "project"),
+    (0x8000, "verity"),
+    (0x10000, "orphan_present"),
+];
+
 /// Attempt to decode the Superblock from the given read stream
 pub fn from_reader<R: Read>(reader: &mut R) -> Result<Ext4, Error> {
-    const SIZE: usize = std::mem::size_of::<Ext4>();
-    let mut data: Ext4 = unsafe { std::mem::zeroed() };
-    let data_sliced = unsafe { slice::from_raw_parts_mut(&mut data as *mut _ as *mut u8, SIZE) };
+    decode::skip(reader, START_POSITION)?;
+    let data: Ext4 = decode::read(reader)?;
 
-    // Drop unwanted bytes (Seek not possible with zstd streamed inputs)
-    io::copy(&mut reader.by_ref().take(START_POSITION), &mut io::sink())?;
-    reader.read_exact(data_sliced)?;
-
-    if data.magic != MAGIC {
+    if data.magic.get() != MAGIC {
         Err(Error::InvalidMagic)
+    } else if !data.is_checksum_valid() {
+        Err(Error::InvalidChecksum)
     } else {
         log::trace!(
             "valid magic field: UUID={} [volume label: \"{}\"]",
@@ -127,6 +183,23 @@ pub fn from_reader<R: Read>(reader: &mut R) -> Result<Ext4, Error> {
     }
 }
 
+impl Ext4 {
+    /// Whether the superblock carries a checksum (`metadata_csum`)
+    pub fn has_checksum(&self) -> bool {
+        self.feature_ro_compat.get() & FEATURE_RO_COMPAT_METADATA_CSUM != 0
+    }
+
+    /// Superblocks without `metadata_csum` have nothing to verify
+    fn is_checksum_valid(&self) -> bool {
+        if !self.has_checksum() {
+            return true;
+        }
+        let bytes = self.as_bytes();
+        self.checksum_type == CHECKSUM_TYPE_CRC32C
+            && decode::crc32c(!0, &bytes[..bytes.len() - 4]) == self.checksum.get()
+    }
+}
+
 impl super::Superblock for Ext4 {
     /// Return the encoded UUID for this superblock
     fn uuid(&self) -> Result<String, Error> {
@@ -138,6 +211,43 @@ impl super::Superblock for Ext4 {
         Ok(std::str::from_utf8(&self.volume_name)?.into())
     }
 
+    fn size(&self) -> Option<u64> {
+        let mut blocks = self.block_counts_lo.get() as u64;
+        if self.feature_incompat.get() & FEATURE_INCOMPAT_64BIT != 0 {
+            blocks |= (self.blocks_count_hi.get() as u64) << 32;
+        }
+        blocks.checked_mul(self.block_size()? as u64)
+    }
+
+    /// 1KiB to 64KiB, anything else is corrupt
+    fn block_size(&self) -> Option<u32> {
+        let log = self.log_block_size.get();
+        (0..=6).contains(&log).then(|| 1024 << log)
+    }
+
+    fn features(&self) -> Vec<&'static str> {
+        decode::flag_names(self.feature_compat.get() as u64, FEATURES_COMPAT)
+            .chain(decode::flag_names(
+                self.feature_incompat.get() as u64,
+                FEATURES_INCOMPAT,
+            ))
+            .chain(decode::flag_names(
+                self.feature_ro_compat.get() as u64,
+                FEATURES_RO_COMPAT,
+            ))
+            .collect()
+    }
+
+    fn last_mounted(&self) -> Option<String> {
+        let path = std::str::from_utf8(&self.last_mounted).ok()?.trim_end_matches('\0');
+        (!path.is_empty()).then(|| path.to_owned())
+    }
+
+    /// A pending journal replay, or errors recorded for fsck
+    fn needs_recovery(&self) -> Option<bool> {
+        Some(self.feature_incompat.get() & FEATURE_INCOMPAT_RECOVER != 0 || self.state.get() & STATE_ERROR_FS != 0)
+    }
+
     fn kind(&self) -> Kind {
         Kind::Ext4
     }
@@ -146,9 +256,17 @@ impl super::Superblock for Ext4 {
 #[cfg(test)]
 mod tests {
 
-    use std::fs;
+    use std::{
+        fs,
+        io::{Cursor, Read},
+    };
 
-    use crate::{ext4::from_reader, Superblock};
+    use zerocopy::FromZeros;
+
+    use crate::{
+        ext4::{from_reader, Ext4, FEATURE_INCOMPAT_64BIT},
+        Error, Superblock,
+    };
 
     #[test]
     fn test_basic() {
@@ -158,5 +276,67 @@ mod tests {
         let label = sb.label().expect("Cannot determine volume name");
         assert_eq!(label, "blsforme testing");
         assert_eq!(sb.uuid().unwrap(), "731af94c-9990-4eed-944d-5d230dbe8a0d");
+        assert_eq!(sb.size(), Some(5120 * 1024));
+        assert_eq!(sb.block_size(), Some(1024));
+        assert_eq!(
+            sb.features(),
+            vec![
+                "has_journal",
+                "ext_attr",
+                "resize_inode",
+                "dir_index",
+                "orphan_file",
+                "filetype",
+                "extent",
+                "64bit",
+                "flex_bg",
+                "metadata_csum_seed",
+                "sparse_super",
+                "large_file",
+                "huge_file",
+                "dir_nlink",
+                "extra_isize",
+                "metadata_csum"
+            ]
+        );
+        assert_eq!(sb.last_mounted(), None);
+        assert_eq!(sb.needs_recovery(), Some(false));
+    }
+
+    #[test]
+    fn test_stale_checksum() {
+        let mut fi = fs::File::open("tests/ext4.img.zst").expect("cannot open ext4 img");
+        let mut memory = vec![];
+        zstd::stream::Decoder::new(&mut fi)
+            .expect("Unable to decode stream")
+            .read_to_end(&mut memory)
+            .expect("Cannot unpack image");
+
+        // Flip a byte of the volume name, leaving the magic intact
+        memory[1024 + 120] ^= 0xff;
+        assert!(matches!(
+            from_reader(&mut Cursor::new(&memory)),
+            Err(Error::InvalidChecksum)
+        ));
+    }
+
+    #[test]
+    fn test_corrupt_geometry() {
+        let mut sb = Ext4::new_zeroed();
+        sb.feature_incompat = FEATURE_INCOMPAT_64BIT.into();
+        sb.block_counts_lo = u32::MAX.into();
+        sb.blocks_count_hi = u32::MAX.into();
+
+        // Overflows rather than wrapping
+        sb.log_block_size = 6.into();
+        assert_eq!(sb.block_size(), Some(64 * 1024));
+        assert_eq!(sb.size(), None);
+
+        // Shifted beyond the largest block size ext4 allows
+        for log in [7, 22, 32, u32::MAX] {
+            sb.log_block_size = log.into();
+            assert_eq!(sb.block_size(), None);
+            assert_eq!(sb.size(), None);
+        }
     }
 }
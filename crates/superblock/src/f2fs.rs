@@ -4,16 +4,13 @@
 
 //! F2FS superblock handling
 
-use crate::{Error, Kind, Superblock};
-use std::{
-    io::{self, Read},
-    ptr, slice,
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
 };
+use std::io::Read;
 use uuid::Uuid;
 
-// Constants to allow us to move away from unsafe{} APIs
-// in future, i.e. read_array(MAX_EXTENSION) ...
-
 const MAX_VOLUME_LEN: usize = 512;
 const MAX_EXTENSION: usize = 64;
 const EXTENSION_LEN: usize = 8;
@@ -23,80 +20,99 @@ const MAX_QUOTAS: usize = 3;
 const MAX_STOP_REASON: usize = 32;
 const MAX_ERRORS: usize = 16;
 
-#[derive(Debug)]
-#[repr(C, packed)]
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
 pub struct F2FS {
-    magic: u32,
-    major_ver: u16,
-    minor_ver: u16,
-    log_sectorsize: u32,
-    log_sectors_per_block: u32,
-    log_blocksize: u32,
-    log_blocks_per_seg: u32,
-    segs_per_sec: u32,
-    secs_per_zone: u32,
-    checksum_offset: u32,
-    block_count: u64,
-    section_count: u32,
-    segment_count: u32,
-    segment_count_ckpt: u32,
-    segment_count_sit: u32,
-    segment_count_nat: u32,
-    segment_count_ssa: u32,
-    segment_count_main: u32,
-    segment0_blkaddr: u32,
-    cp_blkaddr: u32,
-    sit_blkaddr: u32,
-    nat_blkaddr: u32,
-    ssa_blkaddr: u32,
-    main_blkaddr: u32,
-    root_ino: u32,
-    node_ino: u32,
-    meta_ino: u32,
+    magic: Le32,
+    major_ver: Le16,
+    minor_ver: Le16,
+    log_sectorsize: Le32,
+    log_sectors_per_block: Le32,
+    log_blocksize: Le32,
+    log_blocks_per_seg: Le32,
+    segs_per_sec: Le32,
+    secs_per_zone: Le32,
+    checksum_offset: Le32,
+    block_count: Le64,
+    section_count: Le32,
+    segment_count: Le32,
+    segment_count_ckpt: Le32,
+    segment_count_sit: Le32,
+    segment_count_nat: Le32,
+    segment_count_ssa: Le32,
+    segment_count_main: Le32,
+    segment0_blkaddr: Le32,
+    cp_blkaddr: Le32,
+    sit_blkaddr: Le32,
+    nat_blkaddr: Le32,
+    ssa_blkaddr: Le32,
+    main_blkaddr: Le32,
+    root_ino: Le32,
+    node_ino: Le32,
+    meta_ino: Le32,
     uuid: [u8; 16],
-    volume_name: [u16; MAX_VOLUME_LEN],
-    extension_count: u32,
+    volume_name: [Le16; MAX_VOLUME_LEN],
+    extension_count: Le32,
     extension_list: [[u8; EXTENSION_LEN]; MAX_EXTENSION],
-    cp_payload: u32,
+    cp_payload: Le32,
     version: [u8; VERSION_LEN],
     init_version: [u8; VERSION_LEN],
-    feature: u32,
+    feature: Le32,
     encryption_level: u8,
     encryption_pw_salt: [u8; 16],
     devs: [Device; MAX_DEVICES],
-    qf_ino: [u32; MAX_QUOTAS],
+    qf_ino: [Le32; MAX_QUOTAS],
     hot_ext_count: u8,
-    s_encoding: u16,
-    s_encoding_flags: u16,
+    s_encoding: Le16,
+    s_encoding_flags: Le16,
     s_stop_reason: [u8; MAX_STOP_REASON],
     s_errors: [u8; MAX_ERRORS],
     reserved: [u8; 258],
-    crc: u32,
+    crc: Le32,
 }
 
 /// struct f2fs_device
-#[derive(Debug, Clone, Copy)]
-#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
 pub struct Device {
     path: [u8; 64],
-    total_segments: u32,
+    total_segments: Le32,
 }
 
 const MAGIC: u32 = 0xF2F52010;
 const START_POSITION: u64 = 1024;
 
+/// `F2FS_FEATURE_SB_CHKSUM`, the superblock carries a crc32 seeded with the magic
+const FEATURE_SB_CHKSUM: u32 = 0x0800;
+
+/// Feature names as used by `fsck.f2fs`
+const FEATURES: &[(u64, &str)] = &[
+    (0x1, "encrypt"),
+    (0x2, "blkzoned"),
+    (0x4, "atomic_write"),
+    (0x8, "extra_attr"),
+    (0x10, "project_quota"),
+    (0x20, "inode_checksum"),
+    (0x40, "flexible_inline_xattr"),
+    (0x80, "quota_ino"),
+    (0x100, "inode_crtime"),
+    (0x200, "lost_found"),
+    (0x400, "verity"),
+    (0x800, "sb_checksum"),
+    (0x1000, "casefold"),
+    (0x2000, "compression"),
+    (0x4000, "ro"),
+];
+
 /// Attempt to decode the Superblock from the given read stream
 pub fn from_reader<R: Read>(reader: &mut R) -> Result<F2FS, Error> {
-    const SIZE: usize = std::mem::size_of::<F2FS>();
-    let mut data: F2FS = unsafe { std::mem::zeroed() };
-    let data_sliced = unsafe { slice::from_raw_parts_mut(&mut data as *mut _ as *mut u8, SIZE) };
-
-    // Drop unwanted bytes (Seek not possible with zstd streamed inputs)
-    io::copy(&mut reader.by_ref().take(START_POSITION), &mut io::sink())?;
-    reader.read_exact(data_sliced)?;
+    decode::skip(reader, START_POSITION)?;
+    let data: F2FS = decode::read(reader)?;
 
-    if data.magic != MAGIC {
+    if data.magic.get() != MAGIC {
         Err(Error::InvalidMagic)
+    } else if !data.is_checksum_valid() {
+        Err(Error::InvalidChecksum)
     } else {
         log::trace!(
             "valid magic field: UUID={} [volume label: \"{}\"]",
@@ -107,6 +123,22 @@ pub fn from_reader<R: Read>(reader: &mut R) -> Result<F2FS, Error> {
     }
 }
 
+impl F2FS {
+    /// Whether the superblock carries a checksum (`sb_checksum`)
+    pub fn has_checksum(&self) -> bool {
+        self.feature.get() & FEATURE_SB_CHKSUM != 0
+    }
+
+    /// Superblocks without `sb_checksum` have nothing to verify
+    fn is_checksum_valid(&self) -> bool {
+        if !self.has_checksum() {
+            return true;
+        }
+        let offset = self.checksum_offset.get() as usize;
+        offset == std::mem::offset_of!(F2FS, crc) && decode::crc32(MAGIC, &self.as_bytes()[..offset]) == self.crc.get()
+    }
+}
+
 impl Superblock for F2FS {
     /// Return the encoded UUID for this superblock
     fn uuid(&self) -> Result<String, Error> {
@@ -115,12 +147,31 @@ impl Superblock for F2FS {
 
     /// Return the volume label as valid utf16 String
     fn label(&self) -> Result<String, Error> {
-        let vol = unsafe { ptr::read_unaligned(ptr::addr_of!(self.volume_name)) };
+        let vol = self.volume_name.iter().map(|c| c.get()).collect::<Vec<_>>();
         let prelim_label = String::from_utf16(&vol)?;
         // Need valid grapheme step and skip (u16)\0 nul termination in fixed block size
         Ok(prelim_label.trim_end_matches('\0').to_owned())
     }
 
+    fn size(&self) -> Option<u64> {
+        self.block_count.get().checked_mul(self.block_size()? as u64)
+    }
+
+    /// 4KiB, or up to the page size (64KiB) on kernels with larger pages
+    fn block_size(&self) -> Option<u32> {
+        let log = self.log_blocksize.get();
+        (12..=16).contains(&log).then(|| 1 << log)
+    }
+
+    fn features(&self) -> Vec<&'static str> {
+        decode::flag_names(self.feature.get() as u64, FEATURES).collect()
+    }
+
+    /// Errors (or an emergency stop) recorded by the kernel for fsck.f2fs
+    fn needs_recovery(&self) -> Option<bool> {
+        Some(self.s_errors.iter().chain(self.s_stop_reason.iter()).any(|e| *e != 0))
+    }
+
     fn kind(&self) -> Kind {
         Kind::F2FS
     }
@@ -129,8 +180,8 @@ impl Superblock for F2FS {
 #[cfg(test)]
 mod tests {
 
-    use crate::{f2fs::from_reader, Superblock};
-    use std::fs;
+    use crate::{f2fs::from_reader, Error, Superblock};
+    use std::{fs, io::Read};
 
     #[test]
     fn test_basic() {
@@ -140,5 +191,25 @@ mod tests {
         let label = sb.label().expect("Cannot determine volume name");
         assert_eq!(label, "blsforme testing");
         assert_eq!(sb.uuid().unwrap(), "d2c85810-4e75-4274-bc7d-a78267af7443");
+        assert!(!sb.has_checksum());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut fi = fs::File::open("tests/f2fs-sb_checksum.img.zst").expect("cannot open f2fs img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let mut image = vec![];
+        stream.read_to_end(&mut image).expect("Unable to decode image");
+
+        let sb = from_reader(&mut image.as_slice()).expect("Cannot parse superblock");
+        assert!(sb.has_checksum());
+        assert_eq!(sb.uuid().unwrap(), "d2c85810-4e75-4274-bc7d-a78267af7443");
+
+        // Flip a bit in the volume name
+        image[1024 + 0x7c] ^= 1;
+        assert!(matches!(
+            from_reader(&mut image.as_slice()),
+            Err(Error::InvalidChecksum)
+        ));
     }
 }
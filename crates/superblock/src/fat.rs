@@ -0,0 +1,323 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! FAT12/16/32 boot sector handling
+//!
+//! FAT has no magic as such, so we rely on a sane BIOS Parameter Block and
+//! determine the variant from the cluster count, as the specification demands.
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+
+/// Boot sector with the common BIOS Parameter Block, all integers are little endian
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct BootSector {
+    jump: [u8; 3],
+    oem_name: [u8; 8],
+    bytes_per_sector: Le16,
+    sectors_per_cluster: u8,
+    reserved_sectors: Le16,
+    num_fats: u8,
+    root_entries: Le16,
+    total_sectors16: Le16,
+    media: u8,
+    fat_size16: Le16,
+    sectors_per_track: Le16,
+    num_heads: Le16,
+    hidden_sectors: Le32,
+    total_sectors32: Le32,
+
+    /// Extended BPB, layout differs between FAT12/16 and FAT32
+    extended: [u8; 54],
+    boot_code: [u8; 420],
+    signature: [u8; 2],
+}
+
+/// Extended boot signature for the volume ID, label and type fields
+const EXTENDED_SIGNATURE: u8 = 0x29;
+
+/// Offset of the FAT12/16 style fields within the FAT32 extended BPB
+const FAT32_EXTENDED_OFFSET: usize = 28;
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x41615252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x61417272;
+const FSINFO_UNKNOWN: u32 = 0xffffffff;
+
+/// Variant of FAT, decided purely by the number of clusters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Variant::Fat12 => f.write_str("FAT12"),
+            Variant::Fat16 => f.write_str("FAT16"),
+            Variant::Fat32 => f.write_str("FAT32"),
+        }
+    }
+}
+
+/// A FAT filesystem, with the free cluster count where it could be established
+#[derive(Debug)]
+pub struct Fat {
+    boot_sector: BootSector,
+    free_clusters: Option<u32>,
+}
+
+/// Attempt to decode the boot sector from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Fat, Error> {
+    let data: BootSector = decode::read(reader)?;
+
+    if !data.is_valid() {
+        return Err(Error::InvalidMagic);
+    }
+
+    let mut fat = Fat {
+        boot_sector: data,
+        free_clusters: None,
+    };
+    // Best effort, a short device shouldn't stop us identifying the filesystem
+    fat.free_clusters = fat.read_free_clusters(reader).ok().flatten();
+
+    log::trace!(
+        "valid magic field: UUID={} [volume label: \"{}\"]",
+        fat.uuid().unwrap_or_else(|_| "[no serial]".into()),
+        fat.label().unwrap_or_else(|_| "[invalid utf8]".into())
+    );
+    Ok(fat)
+}
+
+impl BootSector {
+    /// Sanity check the BPB in lieu of a magic
+    fn is_valid(&self) -> bool {
+        let bytes_per_sector = self.bytes_per_sector.get();
+        self.signature == [0x55, 0xaa]
+            && matches!(self.jump[0], 0xeb | 0xe9)
+            && bytes_per_sector.is_power_of_two()
+            && (512..=4096).contains(&bytes_per_sector)
+            && self.sectors_per_cluster.is_power_of_two()
+            && self.reserved_sectors.get() > 0
+            && self.num_fats > 0
+            && self.fat_size() > 0
+            && self.total_sectors() > 0
+            && self.cluster_count() > 0
+    }
+
+    fn u16_at(&self, offset: usize) -> u16 {
+        decode::le16(&self.extended, offset).unwrap_or_default()
+    }
+
+    fn u32_at(&self, offset: usize) -> u32 {
+        decode::le32(&self.extended, offset).unwrap_or_default()
+    }
+
+    /// FAT32 is indicated by a zero 16-bit FAT size
+    fn is_fat32_layout(&self) -> bool {
+        self.fat_size16.get() == 0
+    }
+
+    fn fat_size(&self) -> u32 {
+        if self.is_fat32_layout() {
+            self.u32_at(0)
+        } else {
+            self.fat_size16.get() as u32
+        }
+    }
+
+    fn total_sectors(&self) -> u32 {
+        match self.total_sectors16.get() {
+            0 => self.total_sectors32.get(),
+            n => n as u32,
+        }
+    }
+
+    fn root_dir_sectors(&self) -> u32 {
+        let bytes_per_sector = self.bytes_per_sector.get() as u32;
+        (self.root_entries.get() as u32 * 32).div_ceil(bytes_per_sector)
+    }
+
+    /// First sector of the data region
+    fn data_start(&self) -> u32 {
+        self.reserved_sectors.get() as u32 + self.num_fats as u32 * self.fat_size() + self.root_dir_sectors()
+    }
+
+    fn cluster_count(&self) -> u32 {
+        self.total_sectors().saturating_sub(self.data_start()) / self.sectors_per_cluster as u32
+    }
+
+    /// Offset of the volume ID, label and type fields within the extended BPB
+    fn extended_offset(&self) -> usize {
+        if self.is_fat32_layout() {
+            FAT32_EXTENDED_OFFSET
+        } else {
+            0
+        }
+    }
+
+    fn has_extended_signature(&self) -> bool {
+        self.extended[self.extended_offset() + 2] == EXTENDED_SIGNATURE
+    }
+}
+
+impl Fat {
+    /// FAT variant, by cluster count
+    pub fn variant(&self) -> Variant {
+        match self.boot_sector.cluster_count() {
+            n if n < 4085 => Variant::Fat12,
+            n if n < 65525 => Variant::Fat16,
+            _ => Variant::Fat32,
+        }
+    }
+
+    /// Cluster size in bytes
+    pub fn cluster_size(&self) -> u32 {
+        self.boot_sector.bytes_per_sector.get() as u32 * self.boot_sector.sectors_per_cluster as u32
+    }
+
+    /// Number of data clusters
+    pub fn cluster_count(&self) -> u32 {
+        self.boot_sector.cluster_count()
+    }
+
+    /// Size of the filesystem in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.boot_sector.total_sectors() as u64 * self.boot_sector.bytes_per_sector.get() as u64
+    }
+
+    /// Estimated free space in bytes, from FSInfo (FAT32) or the first FAT (FAT12/16)
+    pub fn free_bytes(&self) -> Option<u64> {
+        Some(self.free_clusters? as u64 * self.cluster_size() as u64)
+    }
+
+    /// Volume serial number, when the extended boot signature is present
+    pub fn serial(&self) -> Option<u32> {
+        let bs = &self.boot_sector;
+        bs.has_extended_signature().then(|| bs.u32_at(bs.extended_offset() + 3))
+    }
+
+    /// Count free clusters, with the reader positioned just after the boot sector
+    fn read_free_clusters<R: Read>(&self, reader: &mut R) -> Result<Option<u32>, Error> {
+        let bs = &self.boot_sector;
+        let bytes_per_sector = bs.bytes_per_sector.get() as u64;
+        let consumed = std::mem::size_of::<BootSector>() as u64;
+
+        if self.variant() == Variant::Fat32 {
+            let fs_info = bs.u16_at(12) as u64;
+            if fs_info == 0 || fs_info == 0xffff {
+                return Ok(None);
+            }
+            let Some(skip) = (fs_info * bytes_per_sector).checked_sub(consumed) else {
+                return Ok(None);
+            };
+            decode::skip(reader, skip)?;
+            let mut sector = [0u8; 512];
+            reader.read_exact(&mut sector)?;
+            if decode::le32(&sector, 0) != Some(FSINFO_LEAD_SIGNATURE)
+                || decode::le32(&sector, 484) != Some(FSINFO_STRUCT_SIGNATURE)
+            {
+                return Ok(None);
+            }
+            let Some(free) = decode::le32(&sector, 488) else {
+                return Ok(None);
+            };
+            return Ok((free != FSINFO_UNKNOWN && free <= self.cluster_count()).then_some(free));
+        }
+
+        // FAT12/16 have no FSInfo, so count unused entries in the first FAT
+        let fat_start = bs.reserved_sectors.get() as u64 * bytes_per_sector;
+        decode::skip(reader, fat_start - consumed)?;
+        let mut table = vec![0u8; bs.fat_size() as usize * bytes_per_sector as usize];
+        reader.read_exact(&mut table)?;
+
+        // Entries 0 and 1 are reserved
+        let clusters = 2..self.cluster_count() as usize + 2;
+        let free = match self.variant() {
+            Variant::Fat12 => clusters
+                .filter(|&n| {
+                    let o = n + n / 2;
+                    let pair = u16::from_le_bytes([table[o], *table.get(o + 1).unwrap_or(&0)]);
+                    let entry = if n % 2 == 0 { pair & 0x0fff } else { pair >> 4 };
+                    entry == 0
+                })
+                .count(),
+            _ => clusters.filter(|&n| decode::le16(&table, n * 2) == Some(0)).count(),
+        };
+        Ok(Some(free as u32))
+    }
+}
+
+impl Superblock for Fat {
+    /// Return the volume serial in the `XXXX-XXXX` form used by `UUID=`
+    fn uuid(&self) -> Result<String, Error> {
+        let serial = self.serial().ok_or(Error::UnsupportedFeature)?;
+        Ok(format!("{:04X}-{:04X}", serial >> 16, serial & 0xffff))
+    }
+
+    /// Return the volume label from the boot sector, if one was set
+    fn label(&self) -> Result<String, Error> {
+        let bs = &self.boot_sector;
+        if !bs.has_extended_signature() {
+            return Err(Error::UnsupportedFeature);
+        }
+        let offset = bs.extended_offset() + 7;
+        let label = std::str::from_utf8(&bs.extended[offset..offset + 11])?.trim_end_matches([' ', '\0']);
+        Ok(if label == "NO NAME" { "" } else { label }.to_owned())
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.total_bytes())
+    }
+
+    fn block_size(&self) -> Option<u32> {
+        Some(self.cluster_size())
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Fat
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{
+        fat::{from_reader, Variant},
+        Superblock,
+    };
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/fat32.img.zst").expect("cannot open fat32 img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.variant(), Variant::Fat32);
+        assert_eq!(sb.uuid().unwrap(), "B15F-0A7E");
+        assert_eq!(sb.label().unwrap(), "BLSFORME");
+        assert_eq!(sb.cluster_size(), 512);
+        assert_eq!(sb.total_bytes(), 48 * 1024 * 1024);
+        assert_eq!(sb.free_bytes(), Some((sb.cluster_count() as u64 - 1) * 512));
+    }
+
+    #[test]
+    fn test_fat16() {
+        let mut fi = fs::File::open("tests/fat16.img.zst").expect("cannot open fat16 img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.variant(), Variant::Fat16);
+        assert_eq!(sb.uuid().unwrap(), "5E2D-C0DE");
+        assert_eq!(sb.label().unwrap(), "");
+        assert_eq!(sb.cluster_size(), 2048);
+        assert_eq!(sb.free_bytes(), Some(sb.cluster_count() as u64 * 2048));
+    }
+}
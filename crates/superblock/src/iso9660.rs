@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! ISO9660 volume descriptor handling
+//!
+//! ISO9660 has no UUID, so (like `blkid`) we derive one from the volume
+//! modification or creation timestamp, i.e. `2025-01-31-12-00-00-00`.
+
+use crate::{
+    decode::{self, Be16, Be32, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+
+/// Both-endian `u16`, little endian first
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct Both16 {
+    le: Le16,
+    be: Be16,
+}
+
+/// Both-endian `u32`, little endian first
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct Both32 {
+    le: Le32,
+    be: Be32,
+}
+
+/// Leading portion of a volume descriptor, shared by the primary and Joliet descriptors
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct VolumeDescriptor {
+    kind: u8,
+    magic: [u8; 5],
+    version: u8,
+    flags: u8,
+    system_id: [u8; 32],
+    volume_id: [u8; 32],
+    unused1: [u8; 8],
+    space_size: Both32,
+    escape_sequences: [u8; 32],
+    set_size: Both16,
+    sequence_number: Both16,
+    logical_block_size: Both16,
+    path_table_size: Both32,
+    path_tables: [u8; 16],
+    root_directory: [u8; 34],
+    volume_set_id: [u8; 128],
+    publisher_id: [u8; 128],
+    preparer_id: [u8; 128],
+    application_id: [u8; 128],
+    copyright_file: [u8; 37],
+    abstract_file: [u8; 37],
+    bibliographic_file: [u8; 37],
+    created: [u8; 17],
+    modified: [u8; 17],
+    expires: [u8; 17],
+    effective: [u8; 17],
+    structure_version: u8,
+    unused2: u8,
+    application_data: [u8; 512],
+    reserved: [u8; 653],
+}
+
+const MAGIC: &[u8; 5] = b"CD001";
+
+/// System area is 16 sectors of 2048 bytes
+const START_POSITION: u64 = 16 * 2048;
+
+const DESCRIPTOR_PRIMARY: u8 = 1;
+const DESCRIPTOR_SUPPLEMENTARY: u8 = 2;
+const DESCRIPTOR_TERMINATOR: u8 = 255;
+
+/// Don't wander the whole disc looking for the terminator
+const MAX_DESCRIPTORS: usize = 32;
+
+/// Joliet UCS-2 escape sequences, levels 1 to 3
+const JOLIET_ESCAPES: [&[u8; 3]; 3] = [b"%/@", b"%/C", b"%/E"];
+
+/// An ISO9660 filesystem, with the Joliet volume identifier if present
+#[derive(Debug)]
+pub struct Iso9660 {
+    primary: VolumeDescriptor,
+    joliet_label: Option<String>,
+}
+
+/// Decode a single volume descriptor at the current position of the stream
+fn read_descriptor<R: Read>(reader: &mut R) -> Result<VolumeDescriptor, Error> {
+    let data: VolumeDescriptor = decode::read(reader)?;
+
+    if &data.magic != MAGIC {
+        Err(Error::InvalidMagic)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Attempt to decode the primary volume descriptor from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Iso9660, Error> {
+    decode::skip(reader, START_POSITION)?;
+
+    let mut primary = None;
+    let mut joliet_label = None;
+    for _ in 0..MAX_DESCRIPTORS {
+        let Ok(descriptor) = read_descriptor(reader) else {
+            break;
+        };
+        match descriptor.kind {
+            DESCRIPTOR_PRIMARY if primary.is_none() => primary = Some(descriptor),
+            DESCRIPTOR_SUPPLEMENTARY if JOLIET_ESCAPES.iter().any(|e| descriptor.escape_sequences[..3] == e[..]) => {
+                joliet_label = <[Be16; 16]>::read_from_bytes(&descriptor.volume_id)
+                    .ok()
+                    .and_then(|chars| String::from_utf16(&chars.map(|c| c.get())).ok());
+            }
+            DESCRIPTOR_TERMINATOR => break,
+            _ => {}
+        }
+    }
+
+    let data = Iso9660 {
+        primary: primary.ok_or(Error::InvalidMagic)?,
+        joliet_label,
+    };
+    log::trace!(
+        "valid magic field: UUID={} [volume label: \"{}\"]",
+        data.uuid()?,
+        data.label().unwrap_or_else(|_| "[invalid utf8]".into())
+    );
+    Ok(data)
+}
+
+/// Format a `YYYYMMDDHHMMSScc` timestamp as `YYYY-MM-DD-HH-MM-SS-cc`, unless unset
+fn format_timestamp(timestamp: &[u8; 17]) -> Option<String> {
+    let digits = &timestamp[..16];
+    if !digits.iter().all(u8::is_ascii_digit) || digits.iter().all(|d| *d == b'0') {
+        return None;
+    }
+    let d = std::str::from_utf8(digits).ok()?;
+    Some(format!(
+        "{}-{}-{}-{}-{}-{}-{}",
+        &d[0..4],
+        &d[4..6],
+        &d[6..8],
+        &d[8..10],
+        &d[10..12],
+        &d[12..14],
+        &d[14..16]
+    ))
+}
+
+impl Iso9660 {
+    /// Logical block size, almost always 2048
+    pub fn block_size(&self) -> u16 {
+        self.primary.logical_block_size.le.get()
+    }
+
+    /// Size of the filesystem in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.primary.space_size.le.get() as u64 * self.block_size() as u64
+    }
+}
+
+impl Superblock for Iso9660 {
+    /// Return the modification (or creation) timestamp, as used by `UUID=`
+    fn uuid(&self) -> Result<String, Error> {
+        format_timestamp(&self.primary.modified)
+            .or_else(|| format_timestamp(&self.primary.created))
+            .ok_or(Error::UnsupportedFeature)
+    }
+
+    /// Return the volume identifier, preferring the Joliet descriptor
+    fn label(&self) -> Result<String, Error> {
+        if let Some(label) = self.joliet_label.as_ref() {
+            return Ok(label.trim_end_matches([' ', '\0']).to_owned());
+        }
+        Ok(std::str::from_utf8(&self.primary.volume_id)?
+            .trim_end_matches([' ', '\0'])
+            .to_owned())
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.total_bytes())
+    }
+
+    fn block_size(&self) -> Option<u32> {
+        Some(Iso9660::block_size(self) as u32)
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::ISO9660
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{iso9660::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/iso9660.img.zst").expect("cannot open iso9660 img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.uuid().unwrap(), "2025-03-14-09-26-53-00");
+        assert_eq!(sb.label().unwrap(), "blsforme testing");
+        assert_eq!(sb.block_size(), 2048);
+    }
+}
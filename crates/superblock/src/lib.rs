@@ -8,30 +8,70 @@ use std::io::{self, Read, Seek};
 
 use thiserror::Error;
 
+pub mod bcachefs;
 pub mod btrfs;
+mod decode;
+pub mod erofs;
+pub mod exfat;
 pub mod ext4;
 pub mod f2fs;
+pub mod fat;
+pub mod iso9660;
+pub mod luks1;
 pub mod luks2;
+pub mod lvm2;
+pub mod mdraid;
+pub mod ntfs;
+pub mod squashfs;
+pub mod swap;
+pub mod verity;
 pub mod xfs;
+pub mod zfs;
 
 /// Supported list of superblock readers
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Kind {
+    Bcachefs,
     Btrfs,
+    EROFS,
+    ExFat,
     Ext4,
+    Fat,
+    ISO9660,
+    LUKS1,
     LUKS2,
+    LVM2,
+    MdRaid,
+    NTFS,
     F2FS,
+    Squashfs,
+    Swap,
+    Verity,
     XFS,
+    ZFS,
 }
 
 impl std::fmt::Display for Kind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
+            Kind::Bcachefs => f.write_str("bcachefs"),
             Kind::Btrfs => f.write_str("btrfs"),
+            Kind::EROFS => f.write_str("erofs"),
+            Kind::ExFat => f.write_str("exfat"),
             Kind::Ext4 => f.write_str("ext4"),
+            Kind::Fat => f.write_str("vfat"),
+            Kind::ISO9660 => f.write_str("iso9660"),
+            Kind::LUKS1 => f.write_str("luks1"),
             Kind::LUKS2 => f.write_str("luks2"),
+            Kind::LVM2 => f.write_str("lvm2"),
+            Kind::MdRaid => f.write_str("mdraid"),
+            Kind::NTFS => f.write_str("ntfs"),
             Kind::F2FS => f.write_str("f2fs"),
+            Kind::Squashfs => f.write_str("squashfs"),
+            Kind::Swap => f.write_str("swap"),
+            Kind::Verity => f.write_str("verity"),
             Kind::XFS => f.write_str("xfs"),
+            Kind::ZFS => f.write_str("zfs"),
         }
     }
 }
@@ -45,6 +85,34 @@ pub trait Superblock: std::fmt::Debug + Sync + Send {
 
     /// Get the volume label
     fn label(&self) -> Result<String, self::Error>;
+
+    /// Size of the filesystem in bytes, if recorded
+    fn size(&self) -> Option<u64> {
+        None
+    }
+
+    /// Block (or cluster) size in bytes
+    fn block_size(&self) -> Option<u32> {
+        None
+    }
+
+    /// Enabled feature flags, named as the filesystem's own tools name them (i.e. `metadata_csum`)
+    fn features(&self) -> Vec<&'static str> {
+        vec![]
+    }
+
+    /// Path the filesystem was last mounted at, if recorded
+    fn last_mounted(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the filesystem needs recovery (journal replay or repair) before use
+    ///
+    /// Note: journalled filesystems record a pending replay for as long as they're
+    /// mounted read-write, so this is only conclusive for unmounted or read-only ones.
+    fn needs_recovery(&self) -> Option<bool> {
+        None
+    }
 }
 
 #[derive(Debug, Error)]
@@ -52,6 +120,10 @@ pub enum Error {
     #[error("unknown superblock")]
     UnknownSuperblock,
 
+    // ie an old filesystem signature survived reformatting
+    #[error("ambiguous superblock, found {}", describe_signatures(.0))]
+    AmbiguousSuperblock(Vec<(Kind, u64)>),
+
     // ie label requests on partially implemented superblocks
     #[error("unsupported feature")]
     UnsupportedFeature,
@@ -65,71 +137,240 @@ pub enum Error {
     #[error("invalid magic in superblock")]
     InvalidMagic,
 
+    // ie a stale signature left behind in slack space
+    #[error("invalid checksum in superblock")]
+    InvalidChecksum,
+
     #[error("io: {0}")]
     IO(#[from] io::Error),
 }
 
-/// Attempt to find a superblock decoder for the given reader
-pub fn for_reader<R: Read + Seek>(reader: &mut R) -> Result<Box<dyn Superblock>, Error> {
-    reader.rewind()?;
+impl Error {
+    /// Whether the error merely means no (valid) signature was found, rather
+    /// than an ambiguous device or a failure to read it
+    pub fn is_unrecognised(&self) -> bool {
+        match self {
+            Error::UnknownSuperblock
+            | Error::InvalidMagic
+            | Error::InvalidChecksum
+            | Error::UnsupportedFeature
+            | Error::Utf8Decoding(_)
+            | Error::Utf16Decoding(_) => true,
+            // Too small a device to hold the signature
+            Error::IO(e) => e.kind() == io::ErrorKind::UnexpectedEof,
+            Error::AmbiguousSuperblock(_) => false,
+        }
+    }
+}
+
+fn describe_signatures(signatures: &[(Kind, u64)]) -> String {
+    signatures
+        .iter()
+        .map(|(kind, offset)| format!("{kind} at offset {offset}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// How strongly a signature identifies its format
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    /// No magic, the header merely passed sanity checks (i.e. FAT)
+    Heuristic,
+
+    /// A magic number matched
+    Magic,
 
-    // try ext4
-    if let Ok(block) = ext4::from_reader(reader) {
-        return Ok(Box::new(block));
+    /// A magic number matched and the superblock checksum was verified
+    Checksum,
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self {
+            Confidence::Heuristic => f.write_str("heuristic"),
+            Confidence::Magic => f.write_str("magic"),
+            Confidence::Checksum => f.write_str("checksum"),
+        }
     }
+}
 
-    // try btrfs
-    reader.rewind()?;
-    if let Ok(block) = btrfs::from_reader(reader) {
-        return Ok(Box::new(block));
+/// A signature found by [`probe_all`]
+#[derive(Debug)]
+pub struct Signature {
+    /// Byte offset of the superblock from the start of the device
+    pub offset: u64,
+
+    /// How strongly the signature identifies its format
+    pub confidence: Confidence,
+
+    /// The decoded superblock
+    pub superblock: Box<dyn Superblock>,
+}
+
+impl Signature {
+    /// Convenience accessor for the superblock's kind
+    pub fn kind(&self) -> Kind {
+        self.superblock.kind()
     }
+}
 
-    // try f2fs
+/// Run a single reader from the start of the device
+///
+/// A signature that doesn't decode (or a device too small to hold it) is simply
+/// absent, while any other error is returned: a failing disk must not pass for
+/// an unknown filesystem.
+fn probe<R: Read + Seek, T: Superblock + 'static>(
+    reader: &mut R,
+    from_reader: fn(&mut R) -> Result<T, Error>,
+    locate: fn(&T) -> (u64, Confidence),
+) -> Result<Option<Signature>, Error> {
     reader.rewind()?;
-    if let Ok(block) = f2fs::from_reader(reader) {
-        return Ok(Box::new(block));
+    match from_reader(reader) {
+        Ok(block) => {
+            let (offset, confidence) = locate(&block);
+            Ok(Some(Signature {
+                offset,
+                confidence,
+                superblock: Box::new(block),
+            }))
+        }
+        Err(e) if e.is_unrecognised() => Ok(None),
+        Err(e) => Err(e),
     }
+}
 
-    // try xfs
-    reader.rewind()?;
-    if let Ok(block) = xfs::from_reader(reader) {
-        return Ok(Box::new(block));
+/// Verified checksums are stronger evidence than a bare magic
+fn checksummed(has_checksum: bool) -> Confidence {
+    if has_checksum {
+        Confidence::Checksum
+    } else {
+        Confidence::Magic
     }
+}
 
-    // try luks2
-    reader.rewind()?;
-    if let Ok(block) = luks2::from_reader(reader) {
-        return Ok(Box::new(block));
+/// Report every signature found on the device, in the order they were probed
+///
+/// Unlike [`for_reader`] no attempt is made to choose between them, which is
+/// useful for diagnosing stale signatures left behind by a previous format.
+pub fn probe_all<R: Read + Seek>(reader: &mut R) -> Result<Vec<Signature>, Error> {
+    let signatures = [
+        probe(reader, ext4::from_reader, |b| (1024, checksummed(b.has_checksum())))?,
+        probe(reader, btrfs::from_reader, |b| (65536, checksummed(b.has_checksum())))?,
+        probe(reader, f2fs::from_reader, |b| (1024, checksummed(b.has_checksum())))?,
+        probe(reader, xfs::from_reader, |b| (0, checksummed(b.has_checksum())))?,
+        probe(reader, bcachefs::from_reader, |_| (4096, Confidence::Magic))?,
+        probe(reader, zfs::from_reader, |_| (0, Confidence::Magic))?,
+        probe(reader, erofs::from_reader, |_| (1024, Confidence::Magic))?,
+        probe(reader, squashfs::from_reader, |_| (0, Confidence::Magic))?,
+        probe(reader, iso9660::from_reader, |_| (32768, Confidence::Magic))?,
+        probe(reader, exfat::from_reader, |_| (0, Confidence::Magic))?,
+        probe(reader, ntfs::from_reader, |_| (0, Confidence::Magic))?,
+        probe(reader, luks2::from_reader, |_| (0, Confidence::Magic))?,
+        probe(reader, luks1::from_reader, |_| (0, Confidence::Magic))?,
+        probe(reader, verity::from_reader, |_| (0, Confidence::Magic))?,
+        probe(reader, lvm2::from_reader, |b| {
+            (b.label_sector() * 512, Confidence::Magic)
+        })?,
+        // v1.1 and v1.2
+        probe(reader, mdraid::from_reader, |b| (b.offset(), Confidence::Magic))?,
+        probe(reader, swap::from_reader, |_| (1024, Confidence::Magic))?,
+        // v1.0, living at the end of the device
+        probe(reader, mdraid::from_reader_end, |b| (b.offset(), Confidence::Magic))?,
+        probe(reader, fat::from_reader, |_| (0, Confidence::Heuristic))?,
+    ];
+
+    Ok(signatures.into_iter().flatten().collect())
+}
+
+/// Attempt to find a superblock decoder for the given reader
+///
+/// Much like `blkid`, a device carrying several signatures is refused with
+/// [`Error::AmbiguousSuperblock`] rather than guessing, as picking a stale one
+/// results in an unbootable `root=UUID=`.
+pub fn for_reader<R: Read + Seek>(reader: &mut R) -> Result<Box<dyn Superblock>, Error> {
+    let mut signatures = probe_all(reader)?;
+
+    // RAID members take precedence: v1.0 metadata trails a perfectly readable filesystem
+    if let Some(index) = signatures.iter().position(|s| s.kind() == Kind::MdRaid) {
+        return Ok(signatures.swap_remove(index).superblock);
+    }
+
+    // Heuristic matches only count when nothing better was found
+    if signatures.iter().any(|s| s.confidence > Confidence::Heuristic) {
+        signatures.retain(|s| s.confidence > Confidence::Heuristic);
     }
 
-    Err(Error::UnknownSuperblock)
+    match signatures.len() {
+        0 => Err(Error::UnknownSuperblock),
+        1 => Ok(signatures.remove(0).superblock),
+        _ => {
+            for signature in signatures.iter() {
+                log::warn!(
+                    "{} signature at offset {} ({})",
+                    signature.kind(),
+                    signature.offset,
+                    signature.confidence
+                );
+            }
+            Err(Error::AmbiguousSuperblock(
+                signatures.iter().map(|s| (s.kind(), s.offset)).collect(),
+            ))
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::{
         fs,
-        io::{Cursor, Read},
+        io::{self, Cursor, Read, Seek, SeekFrom},
     };
 
-    use crate::Kind;
+    use crate::{Confidence, Error, Kind};
 
-    use super::for_reader;
+    use super::{for_reader, probe_all};
+
+    /// Unpack a test image in memory
+    fn load_image(fsname: &str) -> Vec<u8> {
+        let mut memory = vec![];
+        let mut fi = fs::File::open(format!("tests/{fsname}.img.zst")).expect("Cannot find test image");
+        zstd::stream::Decoder::new(&mut fi)
+            .expect("Unable to decode stream")
+            .read_to_end(&mut memory)
+            .expect("Could not unpack filesystem in memory");
+        memory
+    }
 
     #[test]
     fn test_determination() {
         let tests = vec![
+            ("bcachefs", Kind::Bcachefs),
             ("btrfs", Kind::Btrfs),
+            ("erofs", Kind::EROFS),
+            ("exfat", Kind::ExFat),
             ("ext4", Kind::Ext4),
             ("f2fs", Kind::F2FS),
-            ("luks+ext4", Kind::LUKS2),
+            ("f2fs-sb_checksum", Kind::F2FS),
+            ("fat16", Kind::Fat),
+            ("fat32", Kind::Fat),
+            ("iso9660", Kind::ISO9660),
+            ("luks1", Kind::LUKS1),
+            ("luks2", Kind::LUKS2),
+            ("lvm2", Kind::LVM2),
+            ("mdraid", Kind::MdRaid),
+            ("mdraid-1.0", Kind::MdRaid),
+            ("ntfs", Kind::NTFS),
+            ("squashfs", Kind::Squashfs),
+            ("swap", Kind::Swap),
+            ("verity", Kind::Verity),
             ("xfs", Kind::XFS),
+            ("zfs", Kind::ZFS),
         ];
 
         // Pre-allocate a buffer for determination tests
         let mut memory: Vec<u8> = Vec::with_capacity(6 * 1024 * 1024);
 
-        for (fsname, _kind) in tests.into_iter() {
+        for (fsname, kind) in tests.into_iter() {
             // Swings and roundabouts: Unpack ztd image in memory to get the Seekable trait we need
             // While each Superblock API is non-seekable, we enforce superblock::for_reader to be seekable
             // to make sure we pre-read a blob and pass it in for rewind/speed.
@@ -144,7 +385,61 @@ mod tests {
             let mut cursor = Cursor::new(&mut memory);
             let block = for_reader(&mut cursor).expect("Failed to find right block implementation");
             eprintln!("{fsname}.img.zstd: superblock matched to {}", block.kind());
-            assert!(matches!(block.kind(), _kind));
+            assert_eq!(block.kind(), kind);
         }
     }
+
+    #[test]
+    fn test_ambiguous() {
+        // Stale ext4 superblock at 1KiB, left behind by mkfs.btrfs
+        let mut memory = load_image("btrfs");
+        memory[1024..2048].copy_from_slice(&load_image("ext4")[1024..2048]);
+
+        let signatures = probe_all(&mut Cursor::new(&memory)).expect("Failed to probe");
+        let found = signatures
+            .iter()
+            .map(|s| (s.kind(), s.offset, s.confidence))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (Kind::Ext4, 1024, Confidence::Checksum),
+                (Kind::Btrfs, 65536, Confidence::Checksum)
+            ]
+        );
+
+        assert!(matches!(
+            for_reader(&mut Cursor::new(&memory)),
+            Err(Error::AmbiguousSuperblock(s)) if s == vec![(Kind::Ext4, 1024), (Kind::Btrfs, 65536)]
+        ));
+    }
+
+    /// Fails every read past the first sector, much like a dying disk
+    struct FailingDisk(Cursor<Vec<u8>>);
+
+    impl Read for FailingDisk {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.position() >= 512 {
+                return Err(io::Error::other("medium error"));
+            }
+            let len = buf.len().min(512 - self.0.position() as usize);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    impl Seek for FailingDisk {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_io_error() {
+        // Short devices merely lack signatures
+        assert!(probe_all(&mut Cursor::new(vec![0u8; 512])).unwrap().is_empty());
+
+        let mut disk = FailingDisk(Cursor::new(load_image("ext4")));
+        assert!(matches!(probe_all(&mut disk), Err(Error::IO(e)) if e.to_string() == "medium error"));
+        assert!(matches!(for_reader(&mut disk), Err(Error::IO(_))));
+    }
 }
@@ -0,0 +1,121 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! LUKS1 superblock support
+
+use crate::{
+    decode::{self, Be16, Be32, FromBytes, Immutable, IntoBytes, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+
+const MAGIC_LEN: usize = 6;
+const CIPHER_NAME_LEN: usize = 32;
+const CIPHER_MODE_LEN: usize = 32;
+const HASH_SPEC_LEN: usize = 32;
+const DIGEST_SIZE: usize = 20;
+const SALT_SIZE: usize = 32;
+const UUID_LEN: usize = 40;
+const NUM_KEYS: usize = 8;
+
+/// struct luks_phdr key slot
+#[derive(Debug, Clone, Copy, FromBytes, IntoBytes, Immutable, Unaligned)]
+#[repr(C)]
+struct KeySlot {
+    active: Be32,
+    password_iterations: Be32,
+    password_salt: [u8; SALT_SIZE],
+    key_material_offset: Be32,
+    stripes: Be32,
+}
+
+/// struct luks_phdr (as seen in cryptsetup), all integers are big endian
+#[derive(Debug, FromBytes, IntoBytes, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Luks1 {
+    magic: [u8; MAGIC_LEN],
+    version: Be16,
+    cipher_name: [u8; CIPHER_NAME_LEN],
+    cipher_mode: [u8; CIPHER_MODE_LEN],
+    hash_spec: [u8; HASH_SPEC_LEN],
+    payload_offset: Be32,
+    key_bytes: Be32,
+    mk_digest: [u8; DIGEST_SIZE],
+    mk_digest_salt: [u8; SALT_SIZE],
+    mk_digest_iterations: Be32,
+    uuid: [u8; UUID_LEN],
+    key_slots: [KeySlot; NUM_KEYS],
+}
+
+const MAGIC: [u8; MAGIC_LEN] = [b'L', b'U', b'K', b'S', 0xba, 0xbe];
+const VERSION: u16 = 1;
+
+/// Attempt to decode the Superblock from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Luks1, Error> {
+    let data: Luks1 = decode::read(reader)?;
+
+    if data.magic != MAGIC || data.version.get() != VERSION {
+        Err(Error::InvalidMagic)
+    } else {
+        log::trace!("valid magic field: UUID={}", data.uuid()?);
+        Ok(data)
+    }
+}
+
+/// Decode a NUL padded string field
+fn decode_str(field: &[u8]) -> Result<String, Error> {
+    Ok(std::str::from_utf8(field)?.trim_end_matches('\0').to_owned())
+}
+
+impl Luks1 {
+    /// Cipher name, i.e. `aes`
+    pub fn cipher_name(&self) -> Result<String, Error> {
+        decode_str(&self.cipher_name)
+    }
+
+    /// Cipher mode, i.e. `xts-plain64`
+    pub fn cipher_mode(&self) -> Result<String, Error> {
+        decode_str(&self.cipher_mode)
+    }
+
+    /// Start of the encrypted payload in 512-byte sectors
+    pub fn payload_offset(&self) -> u32 {
+        self.payload_offset.get()
+    }
+}
+
+impl Superblock for Luks1 {
+    fn kind(&self) -> Kind {
+        Kind::LUKS1
+    }
+
+    /// NOTE: As with LUKS2, the UUID is stored as a string
+    fn uuid(&self) -> Result<String, Error> {
+        decode_str(&self.uuid)
+    }
+
+    /// LUKS1 has no label support
+    fn label(&self) -> Result<String, Error> {
+        Err(Error::UnsupportedFeature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::{luks1::from_reader, Superblock};
+    use std::fs;
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/luks1.img.zst").expect("cannot open luks1 img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.uuid().unwrap(), "6c1f8d2e-3a4b-4c5d-9e6f-7a8b9c0d1e2f");
+        assert_eq!(sb.cipher_name().unwrap(), "aes");
+        assert_eq!(sb.cipher_mode().unwrap(), "xts-plain64");
+        assert_eq!(sb.payload_offset(), 4096);
+        assert!(sb.label().is_err());
+    }
+}
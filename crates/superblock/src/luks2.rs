@@ -4,9 +4,12 @@
 
 //! LUKS2 superblock support
 
-use crate::{Error, Kind, Superblock};
+use crate::{
+    decode::{self, Be16, Be64, FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned},
+    Error, Kind, Superblock,
+};
 use log;
-use std::{io::Read, ptr, slice};
+use std::io::Read;
 
 const MAGIC_LEN: usize = 6;
 const LABEL_LEN: usize = 48;
@@ -16,19 +19,20 @@ const UUID_LEN: usize = 40;
 const CHECKSUM_LEN: usize = 64;
 
 /// Per the `cryptsetup` docs for dm-crypt backed LUKS2, header is at first byte.
-#[derive(Debug)]
-#[repr(C, packed)]
+/// All integers are big endian.
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
 pub struct Luks2 {
     magic: [u8; MAGIC_LEN],
-    version: u16,
-    hdr_size: u64,
-    seqid: u64,
+    version: Be16,
+    hdr_size: Be64,
+    seqid: Be64,
     label: [u8; LABEL_LEN],
     checksum_alg: [u8; CHECKSUM_ALG_LEN],
     salt: [u8; SALT_LEN],
     uuid: [u8; UUID_LEN],
     subsystem: [u8; LABEL_LEN],
-    hdr_offset: u64,
+    hdr_offset: Be64,
     padding: [u8; 184],
     csum: [u8; CHECKSUM_LEN],
     padding4096: [u8; 7 * 512],
@@ -38,17 +42,15 @@ pub struct Luks2 {
 const MAGIC1: [u8; MAGIC_LEN] = [b'L', b'U', b'K', b'S', 0xba, 0xbe];
 const MAGIC2: [u8; MAGIC_LEN] = [b'S', b'K', b'U', b'L', 0xba, 0xbe];
 
+/// Shares the LUKS1 magic, so the (big endian) version must be checked too
+const VERSION: u16 = 2;
+
 /// Attempt to decode the Superblock from the given read stream
 pub fn from_reader<R: Read>(reader: &mut R) -> Result<Luks2, Error> {
-    const SIZE: usize = std::mem::size_of::<Luks2>();
-    let mut data: Luks2 = unsafe { std::mem::zeroed() };
-    let data_sliced = unsafe { slice::from_raw_parts_mut(&mut data as *mut _ as *mut u8, SIZE) };
-    reader.read_exact(data_sliced)?;
-
-    let magic = unsafe { ptr::read_unaligned(ptr::addr_of!(data.magic)) };
+    let data: Luks2 = decode::read(reader)?;
 
-    match magic {
-        MAGIC1 | MAGIC2 => {
+    match data.magic {
+        MAGIC1 | MAGIC2 if data.version.get() == VERSION => {
             log::trace!(
                 "valid magic field: UUID={} [volume label: \"{}\"]",
                 data.uuid()?,
@@ -67,14 +69,12 @@ impl Superblock for Luks2 {
 
     /// NOTE: LUKS2 stores string UUID rather than 128-bit sequence..
     fn uuid(&self) -> Result<String, super::Error> {
-        let uuid = unsafe { ptr::read_unaligned(ptr::addr_of!(self.uuid)) };
-        Ok(std::str::from_utf8(&uuid)?.trim_end_matches('\0').to_owned())
+        Ok(std::str::from_utf8(&self.uuid)?.trim_end_matches('\0').to_owned())
     }
 
     /// NOTE: Label is often empty, set in config instead...
     fn label(&self) -> Result<String, super::Error> {
-        let label = unsafe { ptr::read_unaligned(ptr::addr_of!(self.label)) };
-        Ok(std::str::from_utf8(&label)?.trim_end_matches('\0').to_owned())
+        Ok(std::str::from_utf8(&self.label)?.trim_end_matches('\0').to_owned())
     }
 }
 
@@ -86,9 +86,17 @@ mod tests {
 
     #[test]
     fn test_basic() {
-        let mut fi = fs::File::open("tests/luks+ext4.img.zst").expect("cannot open luks2 img");
+        let mut fi = fs::File::open("tests/luks2.img.zst").expect("cannot open luks2 img");
         let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
         let sb = from_reader(&mut stream).expect("Cannot parse superblock");
-        assert_eq!(sb.uuid().unwrap(), "be373cae-2bd1-4ad5-953f-3463b2e53e59");
+        assert_eq!(sb.uuid().unwrap(), "4b9e2f7a-1c3d-4e5f-8a6b-7c8d9e0f1a2b");
+        assert_eq!(sb.label().unwrap(), "blsforme testing");
+    }
+
+    #[test]
+    fn test_luks1_rejected() {
+        let mut fi = fs::File::open("tests/luks1.img.zst").expect("cannot open luks1 img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        assert!(from_reader(&mut stream).is_err());
     }
 }
@@ -0,0 +1,123 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! LVM2 physical volume label handling
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+
+const SECTOR_SIZE: usize = 512;
+
+/// The label may live in any of the first 4 sectors
+const LABEL_SCAN_SECTORS: usize = 4;
+
+const LABEL_ID: &[u8; 8] = b"LABELONE";
+const LABEL_TYPE: &[u8; 8] = b"LVM2 001";
+const ID_LEN: usize = 32;
+
+/// struct label_header, all integers are little endian
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct LabelHeader {
+    id: [u8; 8],
+    sector_xl: Le64,
+    crc_xl: Le32,
+    offset_xl: Le32,
+    type_: [u8; 8],
+}
+
+/// Leading portion of struct pv_header, followed by the disk areas
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+struct PvHeader {
+    pv_uuid: [u8; ID_LEN],
+    device_size_xl: Le64,
+}
+
+/// An LVM2 physical volume
+#[derive(Debug)]
+pub struct Lvm2 {
+    label: LabelHeader,
+    pv: PvHeader,
+}
+
+/// Attempt to decode the PV label from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Lvm2, Error> {
+    let mut sectors = [0u8; SECTOR_SIZE * LABEL_SCAN_SECTORS];
+    reader.read_exact(&mut sectors)?;
+
+    for sector in sectors.chunks_exact(SECTOR_SIZE) {
+        let label: LabelHeader = decode::read_at(sector, 0).ok_or(Error::InvalidMagic)?;
+        if &label.id != LABEL_ID || &label.type_ != LABEL_TYPE {
+            continue;
+        }
+
+        let pv: PvHeader = decode::read_at(sector, label.offset_xl.get() as usize).ok_or(Error::InvalidMagic)?;
+        let data = Lvm2 { label, pv };
+        log::trace!("valid magic field: UUID={}", data.uuid()?);
+        return Ok(data);
+    }
+
+    Err(Error::InvalidMagic)
+}
+
+impl Lvm2 {
+    /// Size of the underlying device in bytes, as recorded in the PV header
+    pub fn device_size(&self) -> u64 {
+        self.pv.device_size_xl.get()
+    }
+
+    /// Sector the label was found in
+    pub fn label_sector(&self) -> u64 {
+        self.label.sector_xl.get()
+    }
+}
+
+impl Superblock for Lvm2 {
+    /// Return the PV UUID in the LVM2 format, i.e. `bPOhJ4-kLW5-pWYb-2ePD-Kd3c-HXVz-Xz6Y2G`
+    fn uuid(&self) -> Result<String, Error> {
+        let raw = std::str::from_utf8(&self.pv.pv_uuid)?;
+        let mut ret = String::with_capacity(ID_LEN + 6);
+        let mut start = 0;
+        for len in [6, 4, 4, 4, 4, 4, 6] {
+            if start > 0 {
+                ret.push('-');
+            }
+            ret.push_str(&raw[start..start + len]);
+            start += len;
+        }
+        Ok(ret)
+    }
+
+    /// PVs have no label
+    fn label(&self) -> Result<String, Error> {
+        Err(Error::UnsupportedFeature)
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::LVM2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{lvm2::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/lvm2.img.zst").expect("cannot open lvm2 img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.uuid().unwrap(), "bPOhJ4-kLW5-pWYb-2ePD-Kd3c-HXVz-Xz6Y2G");
+        assert_eq!(sb.label_sector(), 1);
+        assert_eq!(sb.device_size(), 1024 * 1024);
+        assert!(sb.label().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Linux software RAID (md) v1.x superblock handling
+//!
+//! Version 1.1 and 1.2 superblocks live at the start of the member device
+//! (offset 0 and 4KiB respectively), while version 1.0 lives at the very end
+//! so that i.e. a RAID1 ESP remains readable by the firmware.
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, LeI32, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::{Read, Seek, SeekFrom};
+
+/// struct mdp_superblock_1 (as seen in the kernel), excluding the trailing dev_roles.
+/// All integers are little endian.
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct MdRaid {
+    magic: Le32,
+    major_version: Le32,
+    feature_map: Le32,
+    pad0: Le32,
+    set_uuid: [u8; 16],
+    set_name: [u8; 32],
+    ctime: Le64,
+    level: LeI32,
+    layout: Le32,
+    size: Le64,
+    chunksize: Le32,
+    raid_disks: Le32,
+    bitmap_offset: Le32,
+    new_level: Le32,
+    reshape_position: Le64,
+    delta_disks: Le32,
+    new_layout: Le32,
+    new_chunk: Le32,
+    new_offset: Le32,
+    data_offset: Le64,
+    data_size: Le64,
+    super_offset: Le64,
+    recovery_offset: Le64,
+    dev_number: Le32,
+    cnt_corrected_read: Le32,
+    device_uuid: [u8; 16],
+    devflags: u8,
+    bblog_shift: u8,
+    bblog_size: Le16,
+    bblog_offset: Le32,
+    utime: Le64,
+    events: Le64,
+    resync_offset: Le64,
+    sb_csum: Le32,
+    max_dev: Le32,
+    pad3: [u8; 32],
+}
+
+const MAGIC: u32 = 0xa92b4efc;
+const MAJOR_VERSION: u32 = 1;
+
+/// Version 1.2 superblock offset, v1.1 lives at 0
+const V1_2_OFFSET: u64 = 4096;
+
+/// Decode a superblock at the current position of the stream
+fn read_superblock<R: Read>(reader: &mut R) -> Result<MdRaid, Error> {
+    let data: MdRaid = decode::read(reader)?;
+
+    if data.magic.get() != MAGIC || data.major_version.get() != MAJOR_VERSION {
+        Err(Error::InvalidMagic)
+    } else {
+        log::trace!(
+            "valid magic field: UUID={} [array name: \"{}\"]",
+            data.uuid()?,
+            data.label().unwrap_or_else(|_| "[invalid utf8]".into())
+        );
+        Ok(data)
+    }
+}
+
+/// Attempt to decode a v1.1 or v1.2 superblock from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<MdRaid, Error> {
+    const SIZE: u64 = std::mem::size_of::<MdRaid>() as u64;
+
+    // v1.1
+    if let Ok(block) = read_superblock(reader) {
+        return Ok(block);
+    }
+
+    // v1.2, drop unwanted bytes (Seek not possible with zstd streamed inputs)
+    decode::skip(reader, V1_2_OFFSET - SIZE)?;
+    read_superblock(reader)
+}
+
+/// Attempt to decode a v1.0 superblock from the end of the device
+///
+/// The superblock is 8KiB from the end, rounded down to a 4KiB boundary
+pub fn from_reader_end<R: Read + Seek>(reader: &mut R) -> Result<MdRaid, Error> {
+    let size = reader.seek(SeekFrom::End(0))?;
+    let sectors = size / 512;
+    if sectors < 16 {
+        return Err(Error::InvalidMagic);
+    }
+    let offset = ((sectors - 16) & !7) * 512;
+    reader.seek(SeekFrom::Start(offset))?;
+    read_superblock(reader)
+}
+
+impl MdRaid {
+    /// RAID level, i.e. `1` for mirrors
+    pub fn level(&self) -> i32 {
+        self.level.get()
+    }
+
+    /// Number of devices in the array
+    pub fn raid_disks(&self) -> u32 {
+        self.raid_disks.get()
+    }
+
+    /// Byte offset of the superblock from the start of the member device
+    pub fn offset(&self) -> u64 {
+        self.super_offset.get() * 512
+    }
+
+    /// Superblock minor version (`1.0`, `1.1` or `1.2`) derived from its location
+    pub fn version(&self) -> &'static str {
+        match self.super_offset.get() {
+            0 => "1.1",
+            8 => "1.2",
+            _ => "1.0",
+        }
+    }
+}
+
+impl Superblock for MdRaid {
+    /// Return the array UUID in the mdadm format, i.e. `3f5d1c2a:b8e94c07:a1d26e4f:90b7c358`
+    ///
+    /// This is the form expected by `rd.md.uuid=`
+    fn uuid(&self) -> Result<String, Error> {
+        Ok(self
+            .set_uuid
+            .chunks_exact(4)
+            .map(|c| c.iter().map(|b| format!("{b:02x}")).collect::<String>())
+            .collect::<Vec<_>>()
+            .join(":"))
+    }
+
+    /// Return the array name, i.e. `host:esp`
+    fn label(&self) -> Result<String, Error> {
+        Ok(std::str::from_utf8(&self.set_name)?.trim_end_matches('\0').to_owned())
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::MdRaid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::{
+        fs,
+        io::{Cursor, Read},
+    };
+
+    use crate::{
+        mdraid::{from_reader, from_reader_end},
+        Superblock,
+    };
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/mdraid.img.zst").expect("cannot open mdraid img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.label().unwrap(), "blsforme:esp");
+        assert_eq!(sb.uuid().unwrap(), "3f5d1c2a:b8e94c07:a1d26e4f:90b7c358");
+        assert_eq!(sb.level(), 1);
+        assert_eq!(sb.raid_disks(), 2);
+        assert_eq!(sb.version(), "1.2");
+    }
+
+    #[test]
+    fn test_metadata_1_0() {
+        let mut fi = fs::File::open("tests/mdraid-1.0.img.zst").expect("cannot open mdraid img");
+        let mut memory = vec![];
+        zstd::stream::Decoder::new(&mut fi)
+            .expect("Unable to decode stream")
+            .read_to_end(&mut memory)
+            .expect("Cannot unpack image");
+
+        assert!(from_reader(&mut Cursor::new(&memory)).is_err());
+        let sb = from_reader_end(&mut Cursor::new(&memory)).expect("Cannot parse superblock");
+        assert_eq!(sb.uuid().unwrap(), "3f5d1c2a:b8e94c07:a1d26e4f:90b7c358");
+        assert_eq!(sb.version(), "1.0");
+    }
+}
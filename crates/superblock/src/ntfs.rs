@@ -0,0 +1,224 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! NTFS boot sector handling
+//!
+//! The volume label lives in the `$VOLUME_NAME` attribute of the `$Volume`
+//! MFT record, so we follow the boot sector to the MFT for it.
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+
+/// NTFS boot sector, all integers are little endian
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct BootSector {
+    jump: [u8; 3],
+    oem_id: [u8; 8],
+    bytes_per_sector: Le16,
+    sectors_per_cluster: u8,
+    reserved_sectors: Le16,
+    zero1: [u8; 3],
+    unused1: Le16,
+    media: u8,
+    zero2: Le16,
+    sectors_per_track: Le16,
+    num_heads: Le16,
+    hidden_sectors: Le32,
+    unused2: Le32,
+    unused3: Le32,
+    total_sectors: Le64,
+    mft_lcn: Le64,
+    mftmirr_lcn: Le64,
+    clusters_per_mft_record: i8,
+    pad1: [u8; 3],
+    clusters_per_index_record: i8,
+    pad2: [u8; 3],
+    volume_serial: Le64,
+    checksum: Le32,
+    boot_code: [u8; 426],
+    signature: [u8; 2],
+}
+
+const MAGIC: &[u8; 8] = b"NTFS    ";
+
+/// MFT record number of `$Volume`
+const MFT_RECORD_VOLUME: u64 = 3;
+const MFT_RECORD_MAGIC: &[u8; 4] = b"FILE";
+
+/// Attribute types
+const ATTR_VOLUME_NAME: u32 = 0x60;
+const ATTR_END: u32 = 0xffffffff;
+
+/// Update sequence fixups are applied per 512 byte stride, regardless of sector size
+const FIXUP_STRIDE: usize = 512;
+
+/// An NTFS filesystem, with the label from `$Volume` (if found)
+#[derive(Debug)]
+pub struct Ntfs {
+    boot_sector: BootSector,
+    label: Option<String>,
+}
+
+/// Attempt to decode the boot sector from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Ntfs, Error> {
+    let data: BootSector = decode::read(reader)?;
+
+    let bytes_per_sector = data.bytes_per_sector.get();
+    if &data.oem_id != MAGIC
+        || data.signature != [0x55, 0xaa]
+        || !bytes_per_sector.is_power_of_two()
+        || !(256..=4096).contains(&bytes_per_sector)
+        || data.sectors_per_cluster == 0
+    {
+        return Err(Error::InvalidMagic);
+    }
+
+    let mut ntfs = Ntfs {
+        boot_sector: data,
+        label: None,
+    };
+    ntfs.label = ntfs.read_label(reader).ok().flatten();
+
+    log::trace!(
+        "valid magic field: UUID={} [volume label: \"{}\"]",
+        ntfs.uuid()?,
+        ntfs.label().unwrap_or_else(|_| "[invalid utf16]".into())
+    );
+    Ok(ntfs)
+}
+
+/// Restore the bytes displaced by the update sequence array
+fn apply_fixups(record: &mut [u8]) -> Option<()> {
+    let usa_offset = decode::le16(record, 4)? as usize;
+    let usa_count = decode::le16(record, 6)? as usize;
+    let usn = record.get(usa_offset..usa_offset + 2)?.to_vec();
+
+    for i in 1..usa_count {
+        let end = i * FIXUP_STRIDE;
+        let fixup = usa_offset + i * 2;
+        if record.get(end - 2..end)? != usn.as_slice() {
+            return None;
+        }
+        let original = record.get(fixup..fixup + 2)?.to_vec();
+        record[end - 2..end].copy_from_slice(&original);
+    }
+    Some(())
+}
+
+/// Find the resident `$VOLUME_NAME` attribute in a fixed up MFT record
+fn find_volume_name(record: &[u8]) -> Option<Result<String, Error>> {
+    let le16 = |o: usize| decode::le16(record, o);
+    let le32 = |o: usize| decode::le32(record, o);
+
+    let mut offset = le16(20)? as usize;
+    loop {
+        let kind = le32(offset)?;
+        let length = le32(offset + 4)? as usize;
+        if kind == ATTR_END || length == 0 {
+            return None;
+        }
+        // Only ever resident
+        if kind == ATTR_VOLUME_NAME && record.get(offset + 8) == Some(&0) {
+            let value_length = le32(offset + 16)? as usize;
+            let value_offset = offset + le16(offset + 20)? as usize;
+            let chars = record
+                .get(value_offset..value_offset + value_length)?
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>();
+            return Some(String::from_utf16(&chars).map_err(Error::from));
+        }
+        offset += length;
+    }
+}
+
+impl Ntfs {
+    /// Cluster size in bytes
+    pub fn cluster_size(&self) -> u32 {
+        self.boot_sector.bytes_per_sector.get() as u32 * self.boot_sector.sectors_per_cluster as u32
+    }
+
+    /// Size of the filesystem in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.boot_sector.total_sectors.get() * self.boot_sector.bytes_per_sector.get() as u64
+    }
+
+    /// Size of each MFT record, negative values are a power of two in bytes
+    pub fn mft_record_size(&self) -> u32 {
+        match self.boot_sector.clusters_per_mft_record {
+            n if n > 0 => n as u32 * self.cluster_size(),
+            n => 1u32.checked_shl(n.unsigned_abs() as u32).unwrap_or(0),
+        }
+    }
+
+    /// Read the label from `$Volume`, with the reader positioned just after the boot sector
+    fn read_label<R: Read>(&self, reader: &mut R) -> Result<Option<String>, Error> {
+        let record_size = self.mft_record_size() as u64;
+        if !(FIXUP_STRIDE as u64..=64 * 1024).contains(&record_size) {
+            return Ok(None);
+        }
+        let mft = self.boot_sector.mft_lcn.get() * self.cluster_size() as u64;
+        let record_start = mft + MFT_RECORD_VOLUME * record_size;
+        let consumed = std::mem::size_of::<BootSector>() as u64;
+        let Some(skip) = record_start.checked_sub(consumed) else {
+            return Ok(None);
+        };
+
+        decode::skip(reader, skip)?;
+        let mut record = vec![0u8; record_size as usize];
+        reader.read_exact(&mut record)?;
+
+        if &record[..4] != MFT_RECORD_MAGIC || apply_fixups(&mut record).is_none() {
+            return Ok(None);
+        }
+        find_volume_name(&record).transpose()
+    }
+}
+
+impl Superblock for Ntfs {
+    /// Return the volume serial as 16 hex digits, as used by `UUID=`
+    fn uuid(&self) -> Result<String, Error> {
+        Ok(format!("{:016X}", self.boot_sector.volume_serial.get()))
+    }
+
+    /// Return the volume label from `$Volume`
+    fn label(&self) -> Result<String, Error> {
+        Ok(self.label.clone().unwrap_or_default())
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.total_bytes())
+    }
+
+    fn block_size(&self) -> Option<u32> {
+        Some(self.cluster_size())
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::NTFS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{ntfs::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/ntfs.img.zst").expect("cannot open ntfs img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.uuid().unwrap(), "5A1E7C3D9B2F4086");
+        assert_eq!(sb.label().unwrap(), "blsforme testing");
+        assert_eq!(sb.cluster_size(), 4096);
+        assert_eq!(sb.mft_record_size(), 1024);
+    }
+}
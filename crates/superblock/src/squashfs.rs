@@ -0,0 +1,149 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! squashfs superblock handling
+//!
+//! Note: squashfs carries neither a UUID nor a label.
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+
+/// struct squashfs_super_block (as seen in the kernel), all integers are little endian
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Squashfs {
+    magic: Le32,
+    inodes: Le32,
+    mkfs_time: Le32,
+    block_size: Le32,
+    fragments: Le32,
+    compression: Le16,
+    block_log: Le16,
+    flags: Le16,
+    no_ids: Le16,
+    s_major: Le16,
+    s_minor: Le16,
+    root_inode: Le64,
+    bytes_used: Le64,
+    id_table_start: Le64,
+    xattr_id_table_start: Le64,
+    inode_table_start: Le64,
+    directory_table_start: Le64,
+    fragment_table_start: Le64,
+    lookup_table_start: Le64,
+}
+
+/// "hsqs"
+const MAGIC: u32 = 0x73717368;
+const MAJOR_VERSION: u16 = 4;
+
+/// Compression algorithm of the data and metadata blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Lzma,
+    Lzo,
+    Xz,
+    Lz4,
+    Zstd,
+    Unknown(u16),
+}
+
+/// Attempt to decode the Superblock from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Squashfs, Error> {
+    let data: Squashfs = decode::read(reader)?;
+
+    if data.magic.get() != MAGIC || data.s_major.get() != MAJOR_VERSION {
+        Err(Error::InvalidMagic)
+    } else {
+        log::trace!(
+            "valid magic field: squashfs {}.{} [{:?}]",
+            data.s_major.get(),
+            data.s_minor.get(),
+            data.compression()
+        );
+        Ok(data)
+    }
+}
+
+impl Squashfs {
+    /// Compression algorithm in use
+    pub fn compression(&self) -> Compression {
+        match self.compression.get() {
+            1 => Compression::Gzip,
+            2 => Compression::Lzma,
+            3 => Compression::Lzo,
+            4 => Compression::Xz,
+            5 => Compression::Lz4,
+            6 => Compression::Zstd,
+            n => Compression::Unknown(n),
+        }
+    }
+
+    /// Data block size in bytes
+    pub fn block_size(&self) -> u32 {
+        self.block_size.get()
+    }
+
+    /// Bytes used by the filesystem, which may be less than the device size
+    pub fn bytes_used(&self) -> u64 {
+        self.bytes_used.get()
+    }
+
+    /// Number of inodes
+    pub fn inodes(&self) -> u32 {
+        self.inodes.get()
+    }
+
+    /// Creation time (seconds since the epoch)
+    pub fn mkfs_time(&self) -> u32 {
+        self.mkfs_time.get()
+    }
+}
+
+impl Superblock for Squashfs {
+    /// squashfs has no UUID
+    fn uuid(&self) -> Result<String, Error> {
+        Err(Error::UnsupportedFeature)
+    }
+
+    /// squashfs has no label
+    fn label(&self) -> Result<String, Error> {
+        Err(Error::UnsupportedFeature)
+    }
+
+    fn size(&self) -> Option<u64> {
+        Some(self.bytes_used())
+    }
+
+    fn block_size(&self) -> Option<u32> {
+        Some(Squashfs::block_size(self))
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Squashfs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::squashfs::{from_reader, Compression};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/squashfs.img.zst").expect("cannot open squashfs img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.compression(), Compression::Zstd);
+        assert_eq!(sb.block_size(), 131072);
+        assert_eq!(sb.inodes(), 1);
+        assert_eq!(sb.bytes_used(), 4096);
+    }
+}
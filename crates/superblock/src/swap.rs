@@ -0,0 +1,98 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Linux swap space header handling
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le32, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+use uuid::Uuid;
+
+/// union swap_header.info (as seen in the kernel)
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Swap {
+    version: Le32,
+    last_page: Le32,
+    nr_badpages: Le32,
+    uuid: [u8; 16],
+    volume_name: [u8; 16],
+    padding: [Le32; 117],
+    badpages: [Le32; 1],
+}
+
+const MAGIC: &[u8; 10] = b"SWAPSPACE2";
+const START_POSITION: u64 = 1024;
+
+/// The magic lives in the last 10 bytes of the first page, for any supported page size
+const PAGE_SIZES: [usize; 5] = [4096, 8192, 16384, 32768, 65536];
+
+/// Attempt to decode the Superblock from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Swap, Error> {
+    const SIZE: usize = std::mem::size_of::<Swap>();
+    decode::skip(reader, START_POSITION)?;
+    let data: Swap = decode::read(reader)?;
+
+    // Page size is unknown, so grab enough to find the magic for the largest one
+    let consumed = START_POSITION as usize + SIZE;
+    let mut page = Vec::with_capacity(PAGE_SIZES[PAGE_SIZES.len() - 1] - consumed);
+    reader
+        .by_ref()
+        .take((PAGE_SIZES[PAGE_SIZES.len() - 1] - consumed) as u64)
+        .read_to_end(&mut page)?;
+
+    let has_magic = PAGE_SIZES.iter().any(|size| {
+        let offset = size - MAGIC.len() - consumed;
+        page.get(offset..offset + MAGIC.len()) == Some(MAGIC)
+    });
+
+    if !has_magic || data.version.get() != 1 {
+        Err(Error::InvalidMagic)
+    } else {
+        log::trace!(
+            "valid magic field: UUID={} [volume label: \"{}\"]",
+            data.uuid()?,
+            data.label().unwrap_or_else(|_| "[invalid utf8]".into())
+        );
+        Ok(data)
+    }
+}
+
+impl Superblock for Swap {
+    /// Return the encoded UUID for this superblock
+    fn uuid(&self) -> Result<String, Error> {
+        Ok(Uuid::from_bytes(self.uuid).hyphenated().to_string())
+    }
+
+    /// Return the volume label as valid utf8
+    fn label(&self) -> Result<String, Error> {
+        Ok(std::str::from_utf8(&self.volume_name)?
+            .trim_end_matches('\0')
+            .to_owned())
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Swap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{swap::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/swap.img.zst").expect("cannot open swap img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        let label = sb.label().expect("Cannot determine volume name");
+        assert_eq!(label, "blsforme swap");
+        assert_eq!(sb.uuid().unwrap(), "5c2a64b6-6d4c-4a5e-9a1c-53f9c2e4d1a7");
+    }
+}
@@ -0,0 +1,123 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! dm-verity hash device superblock handling
+//!
+//! Note: The root hash is deliberately not stored on disk, it must be provided
+//! by the boot loader (i.e. `usrhash=`) for the verification to mean anything.
+
+use crate::{
+    decode::{self, FromBytes, Immutable, IntoBytes, KnownLayout, Le16, Le32, Le64, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
+use uuid::Uuid;
+
+const SALT_SIZE: usize = 256;
+
+/// struct verity_sb (as seen in cryptsetup), all integers are little endian
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
+pub struct Verity {
+    signature: [u8; 8],
+    version: Le32,
+    hash_type: Le32,
+    uuid: [u8; 16],
+    algorithm: [u8; 32],
+    data_block_size: Le32,
+    hash_block_size: Le32,
+    data_blocks: Le64,
+    salt_size: Le16,
+    pad1: [u8; 6],
+    salt: [u8; SALT_SIZE],
+    pad2: [u8; 168],
+}
+
+const MAGIC: &[u8; 8] = b"verity\0\0";
+const VERSION: u32 = 1;
+
+/// Attempt to decode the Superblock from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Verity, Error> {
+    let data: Verity = decode::read(reader)?;
+
+    if &data.signature != MAGIC || data.version.get() != VERSION {
+        Err(Error::InvalidMagic)
+    } else {
+        log::trace!("valid magic field: UUID={}", data.uuid()?);
+        Ok(data)
+    }
+}
+
+impl Verity {
+    /// Hash algorithm, i.e. `sha256`
+    pub fn algorithm(&self) -> Result<String, Error> {
+        Ok(std::str::from_utf8(&self.algorithm)?.trim_end_matches('\0').to_owned())
+    }
+
+    /// Size of the root hash in bits for known algorithms
+    pub fn digest_bits(&self) -> Option<usize> {
+        match self.algorithm().ok()?.as_str() {
+            "sha1" => Some(160),
+            "sha256" => Some(256),
+            "sha512" => Some(512),
+            _ => None,
+        }
+    }
+
+    /// Block size of the data device
+    pub fn data_block_size(&self) -> u32 {
+        self.data_block_size.get()
+    }
+
+    /// Block size of the hash device
+    pub fn hash_block_size(&self) -> u32 {
+        self.hash_block_size.get()
+    }
+
+    /// Number of data blocks covered by the hash tree
+    pub fn data_blocks(&self) -> u64 {
+        self.data_blocks.get()
+    }
+
+    /// Salt used for hashing
+    pub fn salt(&self) -> &[u8] {
+        &self.salt[..(self.salt_size.get() as usize).min(SALT_SIZE)]
+    }
+}
+
+impl Superblock for Verity {
+    /// Return the encoded UUID for this superblock
+    fn uuid(&self) -> Result<String, Error> {
+        Ok(Uuid::from_bytes(self.uuid).hyphenated().to_string())
+    }
+
+    /// Hash devices have no label
+    fn label(&self) -> Result<String, Error> {
+        Err(Error::UnsupportedFeature)
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::Verity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{verity::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/verity.img.zst").expect("cannot open verity img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.uuid().unwrap(), "8a4f2c6e-1d3b-4f5a-9c7e-2b0d4f6a8c1e");
+        assert_eq!(sb.algorithm().unwrap(), "sha256");
+        assert_eq!(sb.digest_bits(), Some(256));
+        assert_eq!(sb.data_block_size(), 4096);
+        assert_eq!(sb.salt().len(), 32);
+    }
+}
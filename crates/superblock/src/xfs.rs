@@ -4,29 +4,31 @@
 
 //! XFS superblock handling
 
-use crate::{Error, Kind, Superblock};
-use std::{io::Read, slice};
+use crate::{
+    decode::{self, Be16, Be32, Be64, BeI64, FromBytes, Immutable, IntoBytes, KnownLayout, Le32, Unaligned},
+    Error, Kind, Superblock,
+};
+use std::io::Read;
 use uuid::Uuid;
 
 // XFS typedefs
-type RfsBlock = u64;
-type RtbXlen = u64;
-type FsBlock = u64;
-type Ino = i64;
-type AgBlock = u32;
-type AgCount = u32;
-type ExtLen = u32;
-type Lsn = i64;
+type RfsBlock = Be64;
+type RtbXlen = Be64;
+type FsBlock = Be64;
+type Ino = BeI64;
+type AgBlock = Be32;
+type AgCount = Be32;
+type ExtLen = Be32;
+type Lsn = BeI64;
 
 const MAX_LABEL_LEN: usize = 12;
 
-/// XFS superblock, aligned to 64-bit
-/// Note: Multi-byte integers (>{i,u}8) must be read as Big Endian
-#[derive(Debug)]
-#[repr(C, align(8))]
+/// XFS superblock, multi-byte integers are big endian (bar the little endian crc)
+#[derive(Debug, FromBytes, IntoBytes, KnownLayout, Immutable, Unaligned)]
+#[repr(C)]
 pub struct XFS {
-    magicnum: u32,
-    blocksize: u32,
+    magicnum: Be32,
+    blocksize: Be32,
     dblocks: RfsBlock,
     rblocks: RfsBlock,
     rextents: RtbXlen,
@@ -40,10 +42,10 @@ pub struct XFS {
     agcount: AgCount,
     rbmblocks: ExtLen,
     logblocks: ExtLen,
-    versionnum: u16,
-    sectsize: u16,
-    inodesize: u16,
-    inopblock: u16,
+    versionnum: Be16,
+    sectsize: Be16,
+    inodesize: Be16,
+    inopblock: Be16,
     fname: [u8; MAX_LABEL_LEN],
     blocklog: u8,
     sectlog: u8,
@@ -54,33 +56,33 @@ pub struct XFS {
     inprogress: u8,
     imax_pct: u8,
 
-    icount: u64,
-    ifree: u64,
-    fdblocks: u64,
-    frextents: u64,
+    icount: Be64,
+    ifree: Be64,
+    fdblocks: Be64,
+    frextents: Be64,
 
     uquotino: Ino,
     gquotino: Ino,
-    qflags: u16,
+    qflags: Be16,
     flags: u8,
     shared_vn: u8,
     inoalignment: ExtLen,
-    unit: u32,
-    width: u32,
+    unit: Be32,
+    width: Be32,
     dirblklog: u8,
     logsectlog: u8,
-    logsectsize: u16,
-    logsunit: u32,
-    features2: u32,
+    logsectsize: Be16,
+    logsunit: Be32,
+    features2: Be32,
 
-    bad_features: u32,
+    bad_features: Be32,
 
-    features_compat: u32,
-    features_ro_cmopat: u32,
-    features_incompat: u32,
-    features_log_incompat: u32,
+    features_compat: Be32,
+    features_ro_cmopat: Be32,
+    features_incompat: Be32,
+    features_log_incompat: Be32,
 
-    crc: u32,
+    crc: Le32,
     spino_align: ExtLen,
 
     pquotino: Ino,
@@ -91,18 +93,37 @@ pub struct XFS {
 /// Magic = 'XFSB'
 const MAGIC: u32 = 0x58465342;
 
+const _: () = assert!(std::mem::size_of::<XFS>() == 264);
+
+/// Version 5 superblocks carry a crc32c over the whole sector
+const VERSION_MASK: u16 = 0x000f;
+const VERSION_5: u16 = 5;
+
+/// `sb_features_incompat`, set by xfs_repair while it's mid-flight
+const FEATURE_INCOMPAT_NEEDSREPAIR: u64 = 0x10;
+
+/// Feature names as used by `xfs_info`
+const FEATURES_RO_COMPAT: &[(u64, &str)] = &[(0x1, "finobt"), (0x2, "rmapbt"), (0x4, "reflink"), (0x8, "inobtcount")];
+const FEATURES_INCOMPAT: &[(u64, &str)] = &[
+    (0x1, "ftype"),
+    (0x2, "sparse"),
+    (0x4, "meta_uuid"),
+    (0x8, "bigtime"),
+    (0x10, "needsrepair"),
+    (0x20, "nrext64"),
+    (0x40, "exchange"),
+    (0x80, "parent"),
+    (0x100, "metadir"),
+];
+
 /// Attempt to decode the Superblock from the given read stream
 pub fn from_reader<R: Read>(reader: &mut R) -> Result<XFS, Error> {
-    const SIZE: usize = std::mem::size_of::<XFS>();
-    let mut data: XFS = unsafe { std::mem::zeroed() };
-    let data_sliced = unsafe { slice::from_raw_parts_mut(&mut data as *mut _ as *mut u8, SIZE) };
-    reader.read_exact(data_sliced)?;
-
-    // Force big-endian
-    data.magicnum = data.magicnum.to_be();
+    let data: XFS = decode::read(reader)?;
 
-    if data.magicnum != MAGIC {
+    if data.magicnum.get() != MAGIC {
         Err(Error::InvalidMagic)
+    } else if !data.is_checksum_valid(reader)? {
+        Err(Error::InvalidChecksum)
     } else {
         log::trace!(
             "valid magic field: UUID={} [volume label: \"{}\"]",
@@ -113,7 +134,64 @@ pub fn from_reader<R: Read>(reader: &mut R) -> Result<XFS, Error> {
     }
 }
 
+impl XFS {
+    /// Whether the superblock carries a checksum (v5 filesystems)
+    pub fn has_checksum(&self) -> bool {
+        self.versionnum.get() & VERSION_MASK == VERSION_5
+    }
+
+    /// Verify the v5 crc, with the reader positioned just after the superblock
+    fn is_checksum_valid<R: Read>(&self, reader: &mut R) -> Result<bool, Error> {
+        if !self.has_checksum() {
+            return Ok(true);
+        }
+
+        // Checksum covers the whole sector, with the crc field itself zeroed
+        let sectsize = self.sectsize.get() as usize;
+        if sectsize < std::mem::size_of::<XFS>() {
+            return Ok(false);
+        }
+        let mut sector = self.as_bytes().to_vec();
+        sector.resize(sectsize, 0);
+        reader.read_exact(&mut sector[std::mem::size_of::<XFS>()..])?;
+
+        let offset = std::mem::offset_of!(XFS, crc);
+        sector[offset..offset + 4].fill(0);
+        Ok(!decode::crc32c(!0, &sector) == self.crc.get())
+    }
+}
+
 impl Superblock for XFS {
+    fn size(&self) -> Option<u64> {
+        self.dblocks.get().checked_mul(self.blocksize.get() as u64)
+    }
+
+    fn block_size(&self) -> Option<u32> {
+        Some(self.blocksize.get())
+    }
+
+    /// The v5 feature fields, with `crc` standing in for the format itself
+    fn features(&self) -> Vec<&'static str> {
+        if !self.has_checksum() {
+            return vec![];
+        }
+        std::iter::once("crc")
+            .chain(decode::flag_names(
+                self.features_ro_cmopat.get() as u64,
+                FEATURES_RO_COMPAT,
+            ))
+            .chain(decode::flag_names(
+                self.features_incompat.get() as u64,
+                FEATURES_INCOMPAT,
+            ))
+            .collect()
+    }
+
+    /// Only an interrupted xfs_repair is recorded, a dirty log isn't visible here
+    fn needs_recovery(&self) -> Option<bool> {
+        (self.has_checksum() && self.features_incompat.get() as u64 & FEATURE_INCOMPAT_NEEDSREPAIR != 0).then_some(true)
+    }
+
     fn kind(&self) -> Kind {
         Kind::XFS
     }
@@ -143,5 +221,11 @@ mod tests {
         let label = sb.label().expect("Cannot determine volume name");
         assert_eq!(label, "BLSFORME");
         assert_eq!(sb.uuid().unwrap(), "45e8a3bf-8114-400f-95b0-380d0fb7d42d");
+        assert_eq!(sb.size(), Some(500 * 1024 * 1024));
+        assert_eq!(sb.block_size(), Some(4096));
+        assert_eq!(
+            sb.features(),
+            vec!["crc", "finobt", "reflink", "inobtcount", "ftype", "sparse", "bigtime"]
+        );
     }
 }
@@ -0,0 +1,213 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! ZFS vdev label handling
+//!
+//! Each member device carries 4 copies of a 256KiB label, the first at the very start.
+//! The label holds an XDR encoded nvlist describing the pool, followed by the uberblock ring.
+
+use crate::{Error, Kind, Superblock};
+use std::io::{self, Read};
+
+/// Blank space and boot block header preceding the nvlist
+const NVLIST_OFFSET: u64 = 16 * 1024;
+const NVLIST_SIZE: usize = 112 * 1024;
+
+/// Uberblock ring, directly after the nvlist
+const UBERBLOCK_RING_SIZE: usize = 128 * 1024;
+const UBERBLOCK_MAGIC: u64 = 0x00bab10c;
+
+/// Smallest uberblock slot (ashift=10), larger ashifts still start at a multiple of this
+const UBERBLOCK_MIN_SIZE: usize = 1024;
+
+const NV_ENCODE_XDR: u8 = 1;
+const DATA_TYPE_UINT64: i32 = 8;
+const DATA_TYPE_STRING: i32 = 9;
+
+/// Pool details from the vdev label of a member device
+#[derive(Debug)]
+pub struct Zfs {
+    name: String,
+    pool_guid: u64,
+    guid: u64,
+    txg: u64,
+    state: u64,
+    version: u64,
+}
+
+/// Big endian cursor over the XDR nvlist
+struct Xdr<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Xdr<'_> {
+    fn bytes(&mut self, len: usize) -> Option<&[u8]> {
+        let ret = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(ret)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    /// Strings are length prefixed and padded to 4 bytes
+    fn string(&mut self) -> Option<&str> {
+        let len = self.i32()? as usize;
+        let padded = len.checked_add(3)? & !3;
+        let raw = self.bytes(padded)?;
+        std::str::from_utf8(&raw[..len]).ok()
+    }
+}
+
+/// Decode the top level pairs we care about, skipping all others (i.e. `vdev_tree`)
+fn parse_nvlist(data: &[u8]) -> Option<Zfs> {
+    // nvs_header_t: encoding, endian, reserved
+    if data.first() != Some(&NV_ENCODE_XDR) {
+        return None;
+    }
+    let mut xdr = Xdr { data, pos: 4 };
+    // nvl_version, nvl_nvflag
+    xdr.bytes(8)?;
+
+    let mut zfs = Zfs {
+        name: String::new(),
+        pool_guid: 0,
+        guid: 0,
+        txg: 0,
+        state: 0,
+        version: 0,
+    };
+    let mut found_name = false;
+    let mut found_guid = false;
+
+    loop {
+        let start = xdr.pos;
+        // Encoded size covers the whole pair, a zero marks the end of the list
+        let encoded_size = xdr.i32()?;
+        if encoded_size <= 0 {
+            break;
+        }
+        let _decoded_size = xdr.i32()?;
+        let name = xdr.string()?.to_owned();
+        let data_type = xdr.i32()?;
+        let _nelem = xdr.i32()?;
+
+        match (name.as_str(), data_type) {
+            ("name", DATA_TYPE_STRING) => {
+                zfs.name = xdr.string()?.to_owned();
+                found_name = true;
+            }
+            ("pool_guid", DATA_TYPE_UINT64) => {
+                zfs.pool_guid = xdr.u64()?;
+                found_guid = true;
+            }
+            ("guid", DATA_TYPE_UINT64) => zfs.guid = xdr.u64()?,
+            ("txg", DATA_TYPE_UINT64) => zfs.txg = xdr.u64()?,
+            ("state", DATA_TYPE_UINT64) => zfs.state = xdr.u64()?,
+            ("version", DATA_TYPE_UINT64) => zfs.version = xdr.u64()?,
+            _ => {}
+        }
+
+        xdr.pos = start.checked_add(encoded_size as usize)?;
+    }
+
+    (found_name && found_guid).then_some(zfs)
+}
+
+/// Attempt to decode the first vdev label from the given read stream
+pub fn from_reader<R: Read>(reader: &mut R) -> Result<Zfs, Error> {
+    // Drop unwanted bytes (Seek not possible with zstd streamed inputs)
+    io::copy(&mut reader.by_ref().take(NVLIST_OFFSET), &mut io::sink())?;
+
+    let mut nvlist = vec![0u8; NVLIST_SIZE];
+    reader.read_exact(&mut nvlist)?;
+    let mut ring = vec![0u8; UBERBLOCK_RING_SIZE];
+    reader.read_exact(&mut ring)?;
+
+    // Written in native endian, so accept either
+    let has_uberblock = ring.chunks_exact(UBERBLOCK_MIN_SIZE).any(|slot| {
+        let magic: [u8; 8] = slot[..8].try_into().expect("slot is large enough");
+        u64::from_le_bytes(magic) == UBERBLOCK_MAGIC || u64::from_be_bytes(magic) == UBERBLOCK_MAGIC
+    });
+    if !has_uberblock {
+        return Err(Error::InvalidMagic);
+    }
+
+    let data = parse_nvlist(&nvlist).ok_or(Error::InvalidMagic)?;
+    log::trace!(
+        "valid magic field: UUID={} [pool name: \"{}\"]",
+        data.uuid()?,
+        data.name
+    );
+    Ok(data)
+}
+
+impl Zfs {
+    /// GUID of the pool, shared by all members
+    pub fn pool_guid(&self) -> u64 {
+        self.pool_guid
+    }
+
+    /// GUID of this vdev
+    pub fn guid(&self) -> u64 {
+        self.guid
+    }
+
+    /// Transaction group the label was last written in
+    pub fn txg(&self) -> u64 {
+        self.txg
+    }
+
+    /// Pool state, `0` when active and `1` when exported
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// On-disk pool version, `5000` for feature flag pools
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl Superblock for Zfs {
+    /// Return the pool GUID in decimal, matching `blkid`
+    fn uuid(&self) -> Result<String, Error> {
+        Ok(self.pool_guid.to_string())
+    }
+
+    /// Return the pool name, as used by `root=ZFS=`
+    fn label(&self) -> Result<String, Error> {
+        Ok(self.name.clone())
+    }
+
+    fn kind(&self) -> Kind {
+        Kind::ZFS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fs;
+
+    use crate::{zfs::from_reader, Superblock};
+
+    #[test]
+    fn test_basic() {
+        let mut fi = fs::File::open("tests/zfs.img.zst").expect("cannot open zfs img");
+        let mut stream = zstd::stream::Decoder::new(&mut fi).expect("Unable to decode stream");
+        let sb = from_reader(&mut stream).expect("Cannot parse superblock");
+        assert_eq!(sb.label().unwrap(), "rpool");
+        assert_eq!(sb.uuid().unwrap(), "11478261563213004345");
+        assert_eq!(sb.guid(), 4387219455630192839);
+        assert_eq!(sb.version(), 5000);
+        assert_eq!(sb.state(), 0);
+    }
+}
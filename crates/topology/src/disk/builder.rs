@@ -7,8 +7,6 @@ use std::{fs, path::PathBuf};
 
 use crate::disk::probe::Probe;
 
-use super::mounts::Table;
-
 /// Builder pattern for a Probe
 pub struct Builder {
     sysfs: PathBuf,
@@ -58,7 +56,7 @@ impl Builder {
             sysfs: fs::canonicalize(self.sysfs)?,
             devfs: fs::canonicalize(self.devfs)?,
             procfs: fs::canonicalize(self.procfs)?,
-            mounts: Table::default(),
+            mountinfo: vec![],
         };
         result.init_scan()?;
         Ok(result)
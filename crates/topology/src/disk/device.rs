@@ -11,10 +11,41 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use superblock::Superblock;
+
 use crate::disk::mounts::MountOption;
 
 use super::probe;
 
+/// A Linux software RAID (md) array
+#[derive(Debug, Clone)]
+pub struct Raid {
+    /// Array UUID in the mdadm format, as used by `rd.md.uuid=`
+    pub uuid: Option<String>,
+
+    /// RAID level, i.e. `raid1`
+    pub level: String,
+
+    /// Superblock metadata version, i.e. `1.2`
+    pub metadata: String,
+
+    /// Member devices
+    pub members: Vec<PathBuf>,
+}
+
+/// A dm-verity mapping
+#[derive(Debug, Clone)]
+pub struct Verity {
+    /// Device holding the verified data
+    pub data: PathBuf,
+
+    /// Device holding the hash tree
+    pub hash: PathBuf,
+
+    /// Root hash (hex), if it could be derived from the partition UUIDs
+    pub roothash: Option<String>,
+}
+
 pub struct BlockDevice<'a> {
     pub kind: Option<superblock::Kind>,
 
@@ -27,6 +58,12 @@ pub struct BlockDevice<'a> {
     /// Block devices living under this device..
     pub children: Vec<BlockDevice<'a>>,
 
+    /// RAID array details, if this is an md device
+    pub raid: Option<Raid>,
+
+    /// Verity details, if this is a dm-verity mapping
+    pub verity: Option<Verity>,
+
     /// What owns us, precious.
     pub(super) probe: &'a probe::Probe,
 
@@ -38,6 +75,34 @@ pub struct BlockDevice<'a> {
 
     // Auxiliary (ignored) device
     pub(super) aux: bool,
+
+    // LVM logical volume (`VG/LV`)
+    pub(super) lvm_volume: Option<String>,
+
+    // LUKS UUID of a mapping whose header is detached from the backing device
+    pub(super) detached_luks: Option<String>,
+
+    // Hibernation target rather than the rootfs
+    pub(super) resume: Option<Resume>,
+
+    // Members of a multi-device btrfs filesystem
+    pub(super) btrfs_devices: Vec<PathBuf>,
+
+    // ZFS dataset (`pool/dataset`) mounted from this pool
+    pub(super) zfs_dataset: Option<String>,
+
+    // Mounted as `/usr` rather than the rootfs
+    pub(super) usr: bool,
+}
+
+/// How a hibernation target holds the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// The whole device is swap
+    Partition,
+
+    /// A swap file, at the given `resume_offset=` (in pages) of its filesystem
+    File { offset: u64 },
 }
 
 impl<'a> BlockDevice<'a> {
@@ -49,86 +114,215 @@ impl<'a> BlockDevice<'a> {
     ) -> Result<Self, super::Error> {
         let path = path.as_ref();
 
-        let block = if let Result::Ok(sb) = probe.get_device_superblock(path) {
-            BlockDevice {
-                kind: Some(sb.kind()),
-                mountpoint: mount.clone(),
-                path: path.to_string_lossy().to_string(),
-                children: vec![],
-                probe,
-                uuid: Some(sb.uuid()?),
-                guid: None,
-                aux,
-            }
-        } else {
-            BlockDevice {
-                kind: None,
-                mountpoint: mount.clone(),
-                path: path.to_string_lossy().to_string(),
-                children: vec![],
-                probe,
-                uuid: None,
-                guid: None,
-                aux,
-            }
+        // Nothing we can emit for the backing device, so at least make some noise
+        if probe.is_plain_crypt(path) {
+            log::warn!(
+                "{} is a plain dm-crypt device without a header or UUID, it must be unlocked via the initrd crypttab",
+                path.display()
+            );
+        }
+
+        let sb = match probe.get_device_superblock(path) {
+            Ok(sb) => Some(sb),
+            // Nothing recognisable, i.e. a whole disk or an unsupported filesystem
+            Err(super::Error::Superblock(e)) if e.is_unrecognised() => None,
+            // Ambiguous signatures or i/o errors must never degrade to `root=/dev/..`
+            Err(e) => return Err(e),
         };
-        Ok(block)
+        Self::with_superblock(probe, path, sb.as_deref(), mount, aux)
+    }
+
+    /// Build the device from an already probed superblock, if any
+    pub(super) fn with_superblock(
+        probe: &'a probe::Probe,
+        path: impl AsRef<Path>,
+        sb: Option<&dyn Superblock>,
+        mount: Option<PathBuf>,
+        aux: bool,
+    ) -> Result<Self, super::Error> {
+        let path = path.as_ref();
+        let uuid = sb.map(|sb| sb.uuid()).transpose()?;
+        let btrfs_devices = match (sb.map(|sb| sb.kind()), uuid.as_ref()) {
+            (Some(superblock::Kind::Btrfs), Some(uuid)) => Some(probe.get_btrfs_devices(uuid))
+                .filter(|d| d.len() > 1)
+                .unwrap_or_default(),
+            _ => vec![],
+        };
+        Ok(BlockDevice {
+            kind: sb.map(|sb| sb.kind()),
+            mountpoint: mount,
+            path: path.to_string_lossy().to_string(),
+            children: vec![],
+            raid: probe.get_raid(path),
+            verity: probe.get_verity(path),
+            probe,
+            uuid,
+            guid: None,
+            aux,
+            lvm_volume: probe.get_lvm_volume(path),
+            detached_luks: probe.get_detached_luks(path),
+            resume: None,
+            btrfs_devices,
+            zfs_dataset: None,
+            usr: false,
+        })
     }
 
     /// Generate a working "root=" style boot line
     pub fn cmd_line(&self) -> String {
         let children = self.children.iter().map(|c| c.cmd_line()).collect::<Vec<_>>().join(" ");
-        let mounts = self
-            .probe
-            .mounts
-            .iter()
-            .map(|m| (PathBuf::from(&m.mountpoint), m))
-            .collect::<HashMap<_, _>>();
-        let mount = self.mountpoint.as_ref().and_then(|m| mounts.get(m));
-        let mount_options = if let Some(mp) = mount {
-            mp.options()
-                .filter_map(|o| {
-                    if let MountOption::Option(k, v) = o {
-                        Some((k, v))
-                    } else {
-                        None
-                    }
-                })
-                .collect::<HashMap<_, _>>()
+        // Per-superblock options (i.e. `subvol=`) of the mount, matched by its unescaped mountpoint
+        let mount_options = self
+            .mountpoint
+            .as_ref()
+            .and_then(|mp| self.probe.mountinfo.iter().rev().find(|m| &m.mountpoint == mp))
+            .map(|info| {
+                info.super_options()
+                    .filter_map(|o| {
+                        if let MountOption::Option(k, v) = o {
+                            Some((k, v))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<HashMap<_, _>>()
+            })
+            .unwrap_or_default();
+
+        let (root_key, flags_key, fstype_key) = if self.usr {
+            ("mount.usr", "mount.usrflags", "mount.usrfstype")
         } else {
-            HashMap::new()
+            ("root", "rootflags", "rootfstype")
+        };
+        // `/usr` has no fstab entry to fall back on, so always state the type
+        let usr_fstype = |kind: &superblock::Kind| {
+            if self.usr {
+                format!(" mount.usrfstype={kind}")
+            } else {
+                String::new()
+            }
         };
+        let verity_hash = self
+            .verity
+            .as_ref()
+            .filter(|_| self.mountpoint.is_some())
+            .and_then(|v| v.roothash.as_ref());
 
-        let local = if let Some(kind) = &self.kind {
+        let local = if let (Some(resume), false) = (self.resume, self.aux) {
+            match (self.uuid.as_ref(), resume) {
+                (Some(uuid), Resume::File { offset }) => format!("resume=UUID={uuid} resume_offset={offset}"),
+                (Some(uuid), Resume::Partition) => format!("resume=UUID={uuid}"),
+                (None, _) => format!("resume={}", &self.path),
+            }
+        } else if let Some(dataset) = &self.zfs_dataset {
+            format!("root=ZFS={dataset}")
+        } else if let Some(hash) = verity_hash {
+            // systemd-veritysetup-generator sets up the mapping under a well known name
+            let (name, hash_key) = if self.usr {
+                ("usr", "usrhash")
+            } else {
+                ("root", "roothash")
+            };
+            let fstype = self.kind.as_ref().map(usr_fstype).unwrap_or_default();
+            format!("{hash_key}={hash} {root_key}=/dev/mapper/{name}{fstype}")
+        } else if let Some(kind) = &self.kind {
             match kind {
+                superblock::Kind::LUKS1 | superblock::Kind::LUKS2 => {
+                    let uuid = self.uuid.as_ref().expect("cannot have luks without uuid");
+                    format!("rd.luks.uuid={}", uuid)
+                }
+                // Activated through `rd.lvm.lv=` of the logical volumes
+                superblock::Kind::LVM2 => String::new(),
+                // Assembled through `rd.md.uuid=` of the array
+                superblock::Kind::MdRaid => String::new(),
+                // Activated through `roothash=` or `usrhash=` of the mapping
+                superblock::Kind::Verity => String::new(),
+                // Imported by pool name through `root=ZFS=`
+                superblock::Kind::ZFS => String::new(),
+                // Backing data of i.e. a verity mapping, only the mapping itself is mounted
+                _ if self.aux && self.mountpoint.is_none() => String::new(),
                 superblock::Kind::Btrfs => {
                     let uuid = self.uuid.as_ref().expect("cannot have btrfs without uuid..");
+                    let mut flags = vec![];
+
+                    // Older kernels only report `subvolid=`, so look up the subvolume path
                     if let Some(subvol) = mount_options.get("subvol") {
-                        format!("root=UUID={} rootfsflags=subvol={}", uuid, subvol)
+                        flags.push(format!("subvol={subvol}"));
+                    } else if let Some(subvolid) = mount_options.get("subvolid") {
+                        match self.mountpoint.as_ref().and_then(|m| self.probe.get_mount_root(m)) {
+                            Some(subvol) => flags.push(format!("subvol={subvol}")),
+                            None => flags.push(format!("subvolid={subvolid}")),
+                        }
+                    }
+
+                    // Multi-device filesystems need every member known before mounting. There's
+                    // no `rd.btrfs` for this: neither dracut nor systemd parse one, and their udev
+                    // rules only wait for `btrfs ready`, so the members are listed via `device=`.
+                    for device in &self.btrfs_devices {
+                        let guid = self
+                            .probe
+                            .get_device_parent(device)
+                            .and_then(|parent| self.probe.get_device_guid(parent, device));
+                        match guid {
+                            Some(guid) => flags.push(format!("device=/dev/disk/by-partuuid/{guid}")),
+                            None => flags.push(format!("device={}", device.display())),
+                        }
+                    }
+
+                    if flags.is_empty() {
+                        format!("{root_key}=UUID={}{}", uuid, usr_fstype(kind))
                     } else {
-                        format!("root=UUID={}", uuid)
+                        format!(
+                            "{root_key}=UUID={} {flags_key}={}{}",
+                            uuid,
+                            flags.join(","),
+                            usr_fstype(kind)
+                        )
                     }
                 }
-                superblock::Kind::LUKS2 => {
-                    let uuid = self.uuid.as_ref().expect("cannot have luks2 without uuid");
-                    format!("rd.luks.uuid={}", uuid)
+                // Every member shares the UUID, only the mounted one needs emitting
+                superblock::Kind::Bcachefs => {
+                    let uuid = self.uuid.as_ref().expect("cannot have bcachefs without uuid");
+                    if self.mountpoint.is_some() {
+                        format!("{root_key}=UUID={} {fstype_key}=bcachefs", uuid)
+                    } else {
+                        String::new()
+                    }
                 }
                 _ => {
                     if let Some(guid) = self.guid.as_ref() {
-                        format!("root=PARTUUID={}", guid)
+                        format!("{root_key}=PARTUUID={}{}", guid, usr_fstype(kind))
                     } else if let Some(uuid) = self.uuid.as_ref() {
-                        format!("root=UUID={}", uuid)
+                        format!("{root_key}=UUID={}{}", uuid, usr_fstype(kind))
                     } else {
                         String::new()
                     }
                 }
             }
         } else if !self.aux {
-            format!("root={}", &self.path)
+            format!("{root_key}={}", &self.path)
         } else {
             String::new()
         };
 
+        // The backing device has no header to emit, so unlock through the mapping
+        let local = match &self.detached_luks {
+            Some(uuid) => format!("{local} rd.luks.uuid={uuid}"),
+            None => local,
+        };
+
+        // Only activate the logical volumes we need
+        let local = match &self.lvm_volume {
+            Some(volume) => format!("{local} rd.lvm.lv={volume}"),
+            None => local,
+        };
+
+        // Likewise only assemble the arrays we need
+        let local = match self.raid.as_ref().and_then(|r| r.uuid.as_ref()) {
+            Some(uuid) => format!("{local} rd.md.uuid={uuid}"),
+            None => local,
+        };
+
         format!("{} {}", local, children).trim().to_owned()
     }
 }
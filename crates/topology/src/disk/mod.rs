@@ -11,8 +11,10 @@ use thiserror::Error;
 mod builder;
 pub use builder::Builder;
 pub mod device;
+pub mod mountinfo;
 pub mod mounts;
 pub mod probe;
+pub mod swaps;
 
 #[derive(Debug, Error)]
 pub enum Error {
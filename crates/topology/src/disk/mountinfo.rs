@@ -0,0 +1,111 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Processing of `/proc/self/mountinfo`
+//!
+//! Unlike `/proc/self/mounts` this exposes the device numbers and the root of
+//! each mount within its filesystem, so bind mounts and btrfs subvolumes can be
+//! told apart.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::mounts::{self, MountOption};
+
+/// A single line of `/proc/self/mountinfo`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountInfo {
+    /// Unique ID of the mount
+    pub mount_id: u32,
+
+    /// ID of the parent mount (or self for the top of the tree)
+    pub parent_id: u32,
+
+    /// Major device number (`st_dev`)
+    pub major: u32,
+
+    /// Minor device number (`st_dev`)
+    pub minor: u32,
+
+    /// Root of the mount within the filesystem, i.e. the btrfs subvolume
+    pub root: PathBuf,
+
+    /// Where the filesystem is mounted
+    pub mountpoint: PathBuf,
+
+    /// Per-mount options
+    pub mount_options: String,
+
+    /// Optional fields, i.e. `shared:1`
+    pub optional_fields: Vec<String>,
+
+    /// The filesystem name
+    pub filesystem: String,
+
+    /// Filesystem specific source, usually the device
+    pub source: String,
+
+    /// Per-superblock options
+    pub super_options: String,
+}
+
+impl MountInfo {
+    /// Convert [`MountInfo::mount_options`] into an iterator of typed options
+    pub fn options(&self) -> impl Iterator<Item = MountOption<'_>> {
+        mounts::parse_options(&self.mount_options)
+    }
+
+    /// Convert [`MountInfo::super_options`] into an iterator of typed options
+    pub fn super_options(&self) -> impl Iterator<Item = MountOption<'_>> {
+        mounts::parse_options(&self.super_options)
+    }
+}
+
+/// Parse a single mountinfo line
+fn parse_line(line: &str) -> Option<MountInfo> {
+    let mut splits = line.split(' ');
+    let mount_id = splits.next()?.parse().ok()?;
+    let parent_id = splits.next()?.parse().ok()?;
+    let (major, minor) = splits.next()?.split_once(':')?;
+    let root = PathBuf::from(mounts::unescape(splits.next()?));
+    let mountpoint = PathBuf::from(mounts::unescape(splits.next()?));
+    let mount_options = splits.next()?.to_owned();
+    let optional_fields = splits
+        .by_ref()
+        .take_while(|f| *f != "-")
+        .map(|f| f.to_owned())
+        .collect();
+
+    Some(MountInfo {
+        mount_id,
+        parent_id,
+        major: major.parse().ok()?,
+        minor: minor.parse().ok()?,
+        root,
+        mountpoint,
+        mount_options,
+        optional_fields,
+        filesystem: splits.next()?.to_owned(),
+        source: mounts::unescape(splits.next()?),
+        super_options: splits.next()?.to_owned(),
+    })
+}
+
+/// Parse the contents of `/proc/self/mountinfo`, skipping malformed lines
+pub fn parse(data: &str) -> Vec<MountInfo> {
+    data.lines().filter_map(parse_line).collect()
+}
+
+/// Load the mount table from the given path (i.e. `/proc/self/mountinfo`)
+///
+/// A missing file is treated as an empty table
+pub fn new_from_path(path: impl AsRef<Path>) -> Result<Vec<MountInfo>, io::Error> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(parse(&data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
@@ -2,25 +2,7 @@
 //
 // SPDX-License-Identifier: MPL-2.0
 
-//! Processing of `/proc/self/mounts` & `/etc/mtab`
-
-use std::{fs, io, path::Path};
-
-/// Encapsulates a `/proc/self/mounts` or mtab file, ignoring fstab specific 5&6 columns
-#[derive(Debug)]
-pub struct Mount<'a> {
-    /// Path of device used for mounting
-    pub device: &'a str,
-
-    /// Where the device was mounted
-    pub mountpoint: &'a str,
-
-    /// The filesystem name
-    pub filesystem: &'a str,
-
-    /// Raw mount options
-    pub opts: &'a str,
-}
+//! Mount options and field escapes shared by the `/proc` mount tables
 
 /// Filesystem specific mount option, i.e `subvol=root`
 #[derive(Debug)]
@@ -47,54 +29,34 @@ impl MountOption<'_> {
     }
 }
 
-impl Mount<'_> {
-    /// Convert [`Mount::opts`] into an iterator of typed options
-    pub fn options(&self) -> impl Iterator<Item = MountOption> {
-        self.opts.split(',').map(|o| {
-            if let Some((k, v)) = o.split_once('=') {
-                MountOption::Option(k, v)
-            } else {
-                MountOption::Flag(o)
-            }
-        })
-    }
-}
-
-/// MountTable for iterating mount points
-#[derive(Debug, Default)]
-pub struct Table {
-    data: String,
+/// Split a comma separated option string into typed options
+pub(super) fn parse_options(opts: &str) -> impl Iterator<Item = MountOption<'_>> {
+    opts.split(',').map(|o| {
+        if let Some((k, v)) = o.split_once('=') {
+            MountOption::Option(k, v)
+        } else {
+            MountOption::Flag(o)
+        }
+    })
 }
 
-impl Table {
-    /// New MountTable parser for string
-    ///
-    /// Arguments:
-    ///
-    /// `data` - Some owned string
-    pub fn new(data: String) -> Self {
-        Self { data }
-    }
-
-    /// Iterate all mount points (no copy)
-    pub fn iter(&self) -> impl Iterator<Item = Mount> {
-        self.data.lines().filter_map(|i| {
-            let mut splits = i.split_ascii_whitespace();
-            Some(Mount {
-                device: splits.next()?,
-                mountpoint: splits.next()?,
-                filesystem: splits.next()?,
-                opts: splits.next()?,
-            })
-        })
-    }
-
-    /// New MountTable parser for file
-    ///
-    /// Arguments:
-    ///
-    /// `path` - Path to load the mtab from (i.e. `/proc/self/mounts`)
-    pub fn new_from_path(path: impl AsRef<Path>) -> Result<Self, io::Error> {
-        Ok(Self::new(fs::read_to_string(path)?))
+/// Decode the octal escapes used by the kernel for whitespace, i.e. `\040`
+pub(super) fn unescape(field: &str) -> String {
+    let mut ret = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let octal = chars.by_ref().take(3).collect::<String>();
+            match u8::from_str_radix(&octal, 8) {
+                Ok(b) => ret.push(b as char),
+                Err(_) => {
+                    ret.push(c);
+                    ret.push_str(&octal);
+                }
+            }
+        } else {
+            ret.push(c);
+        }
     }
+    ret
 }
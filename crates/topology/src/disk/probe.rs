@@ -6,14 +6,19 @@
 
 use std::{
     fs,
-    io::{Cursor, Read},
+    io::{Cursor, Read, Seek},
     path::{Path, PathBuf},
 };
 
 use nix::sys::stat;
 use superblock::Superblock;
 
-use super::{device::BlockDevice, mounts::Table};
+use super::{
+    device::{BlockDevice, Raid, Resume, Verity},
+    mountinfo::{self, MountInfo},
+    mounts::MountOption,
+    swaps::{self, Swap},
+};
 
 /// A Disk probe to query disks
 #[derive(Debug)]
@@ -27,16 +32,15 @@ pub struct Probe {
     /// location of /proc
     pub(super) procfs: PathBuf,
 
-    /// Mountpoints
-    pub mounts: Table,
+    /// Mountpoints with device numbers and filesystem roots
+    pub mountinfo: Vec<MountInfo>,
 }
 
 impl Probe {
     /// Initial startup loads
-    /// TODO: If requested, pvscan/vgscan/lvscan
+    /// Note: LVM volumes are identified through sysfs, no pvscan/vgscan/lvscan required
     pub(super) fn init_scan(&mut self) -> Result<(), super::Error> {
-        let mounts = Table::new_from_path(self.procfs.join("self").join("mounts"))?;
-        self.mounts = mounts;
+        self.mountinfo = mountinfo::new_from_path(self.procfs.join("self").join("mountinfo"))?;
 
         Ok(())
     }
@@ -54,17 +58,32 @@ impl Probe {
 
         // Return by stat path if possible, otherwise fallback to mountpoint device
         if device_path.exists() {
-            Ok(fs::canonicalize(&device_path)?)
-        } else {
-            // Find matching mountpoint
-            let matching_device = self
-                .mounts
-                .iter()
-                .find(|m| PathBuf::from(m.mountpoint) == mountpoint)
-                .ok_or(super::Error::UnknownMount(mountpoint))?;
-            // TODO: Handle `ZFS=`, and composite bcachefs mounts (dev:dev1:dev2)
-            Ok(matching_device.device.into())
+            return Ok(fs::canonicalize(&device_path)?);
         }
+
+        // Later mounts shadow earlier ones, so prefer the last with a matching device number
+        let candidates = self
+            .mountinfo
+            .iter()
+            .rev()
+            .filter(|m| m.mountpoint == mountpoint)
+            .collect::<Vec<_>>();
+        let info = candidates
+            .iter()
+            .find(|m| m.major == stat::major(stat.st_dev) as u32 && m.minor == stat::minor(stat.st_dev) as u32)
+            .or(candidates.first())
+            .ok_or(super::Error::UnknownMount(mountpoint.clone()))?;
+        Ok(info.source.clone().into())
+    }
+
+    /// Return the (last, visible) mountpoint of the given device
+    pub fn get_mountpoint_from_device(&self, device: impl AsRef<Path>) -> Option<PathBuf> {
+        let device = fs::canonicalize(device.as_ref()).ok()?;
+        self.mountinfo
+            .iter()
+            .rev()
+            .find(|m| fs::canonicalize(&m.source).is_ok_and(|s| s == device))
+            .map(|m| m.mountpoint.clone())
     }
 
     /// Retrieve the parent device, such as the disk of a partition, if possible
@@ -113,13 +132,116 @@ impl Probe {
         Ok(ret)
     }
 
+    /// Return the sysfs `dm` directory and target UUID for device-mapper devices
+    fn get_dm_uuid(&self, device: impl AsRef<Path>) -> Option<(PathBuf, String)> {
+        let device = fs::canonicalize(device.as_ref()).ok()?;
+        let dm = self
+            .sysfs
+            .join("class")
+            .join("block")
+            .join(device.file_name()?)
+            .join("dm");
+        let uuid = fs::read_to_string(dm.join("uuid")).ok()?.trim().to_owned();
+        Some((dm, uuid))
+    }
+
+    /// Returns true for plain dm-crypt targets, which have no on-disk header (or UUID)
+    pub fn is_plain_crypt(&self, device: impl AsRef<Path>) -> bool {
+        self.get_dm_uuid(device)
+            .is_some_and(|(_, uuid)| uuid.starts_with("CRYPT-PLAIN-"))
+    }
+
+    /// For LUKS mappings with a detached header, return the LUKS UUID as used by `rd.luks.uuid=`
+    ///
+    /// The header lives elsewhere, so the UUID is only known from the mapping itself
+    /// (`CRYPT-LUKS2-<uuid>-<name>`). Attached headers are found on the backing device.
+    pub fn get_detached_luks(&self, device: impl AsRef<Path>) -> Option<String> {
+        let (dm, uuid) = self.get_dm_uuid(device)?;
+        let uuid = uuid
+            .strip_prefix("CRYPT-LUKS1-")
+            .or_else(|| uuid.strip_prefix("CRYPT-LUKS2-"))?
+            .split('-')
+            .next()?;
+        if uuid.len() != 32 || !uuid.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let attached = fs::read_dir(dm.parent()?.join("slaves"))
+            .ok()?
+            .filter_map(|e| e.ok())
+            .any(|e| {
+                self.get_device_superblock(self.devfs.join(e.file_name()))
+                    .is_ok_and(|sb| matches!(sb.kind(), superblock::Kind::LUKS1 | superblock::Kind::LUKS2))
+            });
+        if attached {
+            None
+        } else {
+            Some(format!(
+                "{}-{}-{}-{}-{}",
+                &uuid[0..8],
+                &uuid[8..12],
+                &uuid[12..16],
+                &uuid[16..20],
+                &uuid[20..32]
+            ))
+        }
+    }
+
+    /// For device-mapper LVM logical volumes, return the `VG/LV` name as used by `rd.lvm.lv=`
+    pub fn get_lvm_volume(&self, device: impl AsRef<Path>) -> Option<String> {
+        let (dm, uuid) = self.get_dm_uuid(device)?;
+        if !uuid.starts_with("LVM-") {
+            return None;
+        }
+        let name = fs::read_to_string(dm.join("name")).ok()?;
+        let (vg, lv) = split_lvm_name(name.trim())?;
+        Some(format!("{vg}/{lv}"))
+    }
+
+    /// Member devices of a mounted btrfs filesystem, as registered with the kernel
+    pub fn get_btrfs_devices(&self, uuid: &str) -> Vec<PathBuf> {
+        let dir = self.sysfs.join("fs").join("btrfs").join(uuid).join("devices");
+        let mut devices = fs::read_dir(dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| Some(self.devfs.join(e.ok()?.file_name())))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        devices.sort();
+        devices
+    }
+
+    /// Return the root of the mount within its filesystem (i.e. the btrfs subvolume)
+    pub fn get_mount_root(&self, mountpoint: impl AsRef<Path>) -> Option<String> {
+        let mountpoint = mountpoint.as_ref();
+        self.mountinfo
+            .iter()
+            .rev()
+            .find(|m| m.mountpoint == mountpoint)
+            .map(|m| m.root.to_string_lossy().to_string())
+    }
+
     /// Scan superblock of the device for `UUID=` parameter
     pub fn get_device_superblock(&self, path: impl AsRef<Path>) -> Result<Box<dyn Superblock>, super::Error> {
         let path = path.as_ref();
         log::trace!("Querying superblock information for {}", path.display());
-        let fi = fs::File::open(path)?;
+        let mut fi = fs::File::open(path)?;
+
+        // mdraid v1.0 lives at the end of the device, beyond our buffer. The member
+        // data starts at offset 0 (i.e. a FAT ESP on RAID1), so it must take precedence.
+        match superblock::mdraid::from_reader_end(&mut fi) {
+            Ok(sb) => {
+                log::trace!("detected superblock: {}", sb.kind());
+                return Ok(Box::new(sb));
+            }
+            Err(e) if !e.is_unrecognised() => return Err(e.into()),
+            Err(_) => {}
+        }
+
+        fi.rewind()?;
         let mut buffer: Vec<u8> = Vec::with_capacity(2 * 1024 * 1024);
-        fi.take(2 * 1024 * 1024).read_to_end(&mut buffer)?;
+        fi.by_ref().take(2 * 1024 * 1024).read_to_end(&mut buffer)?;
         let mut cursor = Cursor::new(&buffer);
         let sb = superblock::for_reader(&mut cursor)?;
         log::trace!("detected superblock: {}", sb.kind());
@@ -127,12 +249,207 @@ impl Probe {
         Ok(sb)
     }
 
+    /// For md arrays, return the RAID level, metadata version and member devices
+    pub fn get_raid(&self, device: impl AsRef<Path>) -> Option<Raid> {
+        let device = fs::canonicalize(device.as_ref()).ok()?;
+        let sysfs_path = self.sysfs.join("class").join("block").join(device.file_name()?);
+        let md = sysfs_path.join("md");
+        let level = fs::read_to_string(md.join("level")).ok()?.trim().to_owned();
+        let metadata = fs::read_to_string(md.join("metadata_version"))
+            .map(|m| m.trim().to_owned())
+            .unwrap_or_default();
+
+        let mut members = fs::read_dir(sysfs_path.join("slaves"))
+            .ok()?
+            .filter_map(|e| Some(self.devfs.join(e.ok()?.file_name())))
+            .collect::<Vec<_>>();
+        members.sort();
+
+        // The array UUID is only recorded in the member superblocks
+        let uuid = members.iter().find_map(|m| {
+            self.get_device_superblock(m)
+                .ok()
+                .filter(|sb| sb.kind() == superblock::Kind::MdRaid)?
+                .uuid()
+                .ok()
+        });
+
+        Some(Raid {
+            uuid,
+            level,
+            metadata,
+            members,
+        })
+    }
+
+    /// For dm-verity mappings, return the data and hash devices along with the root hash
+    ///
+    /// The root hash isn't stored on disk, so it is derived from the partition UUIDs per the
+    /// Discoverable Partitions Specification: the data partition UUID holds the first 128 bits
+    /// and the hash partition UUID the final 128 bits of a sha256 root hash.
+    pub fn get_verity(&self, device: impl AsRef<Path>) -> Option<Verity> {
+        let device = device.as_ref();
+        let (dm, uuid) = self.get_dm_uuid(device)?;
+        if !uuid.starts_with("CRYPT-VERITY-") {
+            return None;
+        }
+
+        let slaves = fs::read_dir(dm.parent()?.join("slaves"))
+            .ok()?
+            .filter_map(|e| Some(self.devfs.join(e.ok()?.file_name())))
+            .collect::<Vec<_>>();
+        let (hash, sb) = slaves.iter().find_map(|s| {
+            let sb = superblock::verity::from_reader(&mut fs::File::open(s).ok()?).ok()?;
+            Some((s.clone(), sb))
+        })?;
+        // Hash tree may also live at the end of the data device
+        let data = slaves.iter().find(|s| **s != hash).unwrap_or(&hash).clone();
+
+        let guid = |d: &Path| {
+            self.get_device_parent(d)
+                .and_then(|p| self.get_device_guid(p, d))
+                .map(|g| g.replace('-', ""))
+        };
+        let roothash = if sb.digest_bits() == Some(256) && data != hash {
+            guid(&data).zip(guid(&hash)).map(|(d, h)| format!("{d}{h}"))
+        } else {
+            None
+        };
+        if roothash.is_none() {
+            log::warn!(
+                "Unable to derive the root hash of verity device {}, it must be provided manually",
+                device.display()
+            );
+        }
+
+        Some(Verity { data, hash, roothash })
+    }
+
+    /// Return the md array that the device is a member of, if any
+    pub fn get_raid_holder(&self, device: impl AsRef<Path>) -> Option<PathBuf> {
+        let device = fs::canonicalize(device.as_ref()).ok()?;
+        let holders = self
+            .sysfs
+            .join("class")
+            .join("block")
+            .join(device.file_name()?)
+            .join("holders");
+        fs::read_dir(holders)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .find(|name| name.starts_with("md"))
+            .and_then(|name| fs::canonicalize(self.devfs.join(name)).ok())
+    }
+
     /// Determine the composite rootfs device for the given mountpoint,
     /// building a set of superblocks and necessary `/proc/cmdline` arguments
-    pub fn get_rootfs_device(&self, path: impl AsRef<Path>) -> Result<BlockDevice, super::Error> {
+    pub fn get_rootfs_device(&self, path: impl AsRef<Path>) -> Result<BlockDevice<'_>, super::Error> {
         let path = path.as_ref();
         let device = self.get_device_from_mountpoint(path)?;
 
+        // ZFS datasets have no device of their own, only the pool members
+        if self.get_mount_filesystem(path).as_deref() == Some("zfs") {
+            return self.get_zfs_device(device, path);
+        }
+
+        // Multi-device filesystems (bcachefs) are mounted as `dev1:dev2`
+        let members = split_device_spec(&device);
+        let Some((first, others)) = members.split_first() else {
+            return self.get_composite_device(device, Some(path.into()));
+        };
+
+        let mut block = self.get_composite_device(first.clone(), Some(path.into()))?;
+        for member in others {
+            block.children.push(self.get_composite_device(member.clone(), None)?);
+        }
+        Ok(block)
+    }
+
+    /// Return the filesystem type mounted at the given mountpoint
+    pub fn get_mount_filesystem(&self, mountpoint: impl AsRef<Path>) -> Option<String> {
+        let mountpoint = fs::canonicalize(mountpoint.as_ref()).ok()?;
+        self.mountinfo
+            .iter()
+            .rev()
+            .find(|m| m.mountpoint == mountpoint)
+            .map(|m| m.filesystem.clone())
+    }
+
+    /// Whether the given mountpoint is mounted read-only
+    pub fn is_mounted_read_only(&self, mountpoint: impl AsRef<Path>) -> bool {
+        let Ok(mountpoint) = fs::canonicalize(mountpoint.as_ref()) else {
+            return false;
+        };
+        let read_only = |o: MountOption<'_>| matches!(o, MountOption::Flag("ro"));
+        self.mountinfo
+            .iter()
+            .rev()
+            .find(|m| m.mountpoint == mountpoint)
+            .is_some_and(|m| m.options().any(read_only) || m.super_options().any(read_only))
+    }
+
+    /// Find the member devices of the named ZFS pool by their vdev labels
+    ///
+    /// Note: This reads the superblock of every block device, so is only used for ZFS roots
+    pub fn get_zfs_members(&self, pool: &str) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(self.sysfs.join("class").join("block")) else {
+            return vec![];
+        };
+        let mut members = entries
+            .filter_map(|e| Some(self.devfs.join(e.ok()?.file_name())))
+            .filter(|device| {
+                self.get_device_superblock(device)
+                    .is_ok_and(|sb| sb.kind() == superblock::Kind::ZFS && sb.label().is_ok_and(|name| name == pool))
+            })
+            .collect::<Vec<_>>();
+        members.sort();
+        members
+    }
+
+    /// Build the composite device for a ZFS dataset (i.e. `rpool/ROOT/serpent`), with
+    /// each pool member (and its backing devices) as children
+    fn get_zfs_device(&self, dataset: PathBuf, mountpoint: &Path) -> Result<BlockDevice<'_>, super::Error> {
+        let dataset = dataset.to_string_lossy().to_string();
+        let pool = dataset.split('/').next().unwrap_or_default();
+        let members = self.get_zfs_members(pool);
+        if members.is_empty() {
+            log::warn!("Unable to find any member devices of ZFS pool {pool}");
+        }
+
+        // The dataset is no device of its own, so there's no superblock to probe
+        let mut block = BlockDevice::with_superblock(self, &dataset, None, Some(mountpoint.into()), false)?;
+        block.zfs_dataset = Some(dataset);
+        for member in members {
+            block.children.push(self.get_composite_device(member, None)?);
+        }
+        Ok(block)
+    }
+
+    /// Determine the composite device for `/usr` when it is a separate mount relative to
+    /// the given root, as in image based deployments
+    pub fn get_usr_device(&self, root: impl AsRef<Path>) -> Result<Option<BlockDevice<'_>>, super::Error> {
+        let path = root.as_ref().join("usr");
+        if self.get_mount_filesystem(&path).is_none() {
+            return Ok(None);
+        }
+
+        let device = self.get_device_from_mountpoint(&path)?;
+        let mut block = self.get_composite_device(device, Some(path))?;
+        block.usr = true;
+        for child in block.children.iter_mut() {
+            child.usr = true;
+        }
+        Ok(Some(block))
+    }
+
+    /// Build the composite device for `device`, including any backing devices
+    /// (i.e. LUKS) in the chain
+    fn get_composite_device(
+        &self,
+        device: PathBuf,
+        mountpoint: Option<PathBuf>,
+    ) -> Result<BlockDevice<'_>, super::Error> {
         // Scan GPT for PartUUID
         let guid = if let Some(parent) = self.get_device_parent(&device) {
             self.get_device_guid(parent, &device)
@@ -147,34 +464,89 @@ impl Probe {
         let tip = custodials.pop().expect("we just added this..");
         let name = tip.to_string_lossy().to_string();
 
-        let mut block = BlockDevice::new(self, &name, None, true)?;
+        // Not layered, so the tip is the mounted device itself
+        let tip_mount = if tip == device { mountpoint.clone() } else { None };
+        let mut block = BlockDevice::new(self, &name, tip_mount, true)?;
         block.children = custodials
             .iter()
-            .flat_map(|c| {
+            .map(|c| {
                 if *c == device {
-                    BlockDevice::new(self, c.clone(), Some(path.into()), false)
+                    BlockDevice::new(self, c.clone(), mountpoint.clone(), false)
                 } else {
                     BlockDevice::new(self, c.clone(), None, true)
                 }
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>, _>>()?;
         block.guid = guid;
 
         Ok(block)
     }
 
+    /// Active swap areas from `/proc/swaps`
+    pub fn get_swaps(&self) -> Result<Vec<Swap>, super::Error> {
+        Ok(swaps::new_from_path(self.procfs.join("swaps"))?)
+    }
+
+    /// Determine the hibernation device from the active swap areas, building the
+    /// necessary `resume=` (and i.e. `rd.luks.uuid=`) arguments
+    ///
+    /// Swap partitions are preferred over swap files, then by priority. Volatile
+    /// swap such as zram is never considered.
+    pub fn get_resume_device(&self) -> Result<Option<BlockDevice<'_>>, super::Error> {
+        let mut candidates = self
+            .get_swaps()?
+            .into_iter()
+            .filter(|s| !s.is_volatile())
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|s| (s.kind != swaps::Kind::Partition, -s.priority));
+        let Some(swap) = candidates.into_iter().next() else {
+            return Ok(None);
+        };
+        log::trace!("resume swap: {swap:?}");
+
+        let (device, resume) = match swap.kind {
+            swaps::Kind::Partition => {
+                // Keep any subdirectory (i.e. `/dev/mapper/vg-swap`), the link is resolved below
+                let relative = swap.path.strip_prefix("/dev").unwrap_or(&swap.path);
+                (self.devfs.join(relative), Resume::Partition)
+            }
+            swaps::Kind::File => {
+                let device = self.get_device_from_mountpoint(&swap.path)?;
+                let sb = self.get_device_superblock(&device)?;
+                if sb.kind() == superblock::Kind::Btrfs {
+                    log::warn!(
+                        "Cannot compute resume_offset for btrfs swapfile {}",
+                        swap.path.display()
+                    );
+                    return Ok(None);
+                }
+                let offset = swaps::swapfile_offset(&swap.path)?;
+                (device, Resume::File { offset })
+            }
+        };
+
+        let mut block = self.get_composite_device(fs::canonicalize(device)?, None)?;
+        // Not layered, so the tip is the swap device itself
+        if block.children.is_empty() {
+            block.aux = false;
+            block.resume = Some(resume);
+        }
+        for child in block.children.iter_mut().filter(|c| !c.aux) {
+            child.resume = Some(resume);
+        }
+        Ok(Some(block))
+    }
+
+    /// Return the partition number of the device within its parent disk
+    fn get_partition_number(&self, device: impl AsRef<Path>) -> Option<u32> {
+        let device = fs::canonicalize(device.as_ref()).ok()?;
+        let sysfs_path = fs::canonicalize(self.sysfs.join("class").join("block").join(device.file_name()?)).ok()?;
+        str::parse::<u32>(fs::read_to_string(sysfs_path.join("partition")).ok()?.trim()).ok()
+    }
+
     /// For GPT disks return the PartUUID (GUID)
     pub fn get_device_guid(&self, parent: impl AsRef<Path>, path: impl AsRef<Path>) -> Option<String> {
-        let device = fs::canonicalize(path.as_ref()).ok()?;
-        let sysfs_path = fs::canonicalize(
-            device
-                .file_name()
-                .map(|f| self.sysfs.join("class").join("block").join(f))
-                .ok_or_else(|| super::Error::InvalidDevice(device.clone()))
-                .ok()?,
-        )
-        .ok()?;
-        let partition = str::parse::<u32>(fs::read_to_string(sysfs_path.join("partition")).ok()?.trim()).ok()?;
+        let partition = self.get_partition_number(path)?;
         let fi = fs::File::open(parent).ok()?;
         let gpt_header = gpt::GptConfig::new()
             .writable(false)
@@ -186,4 +558,118 @@ impl Probe {
             .get(&partition)
             .map(|partition| partition.part_guid.hyphenated().to_string())
     }
+
+    /// Returns true if systemd-gpt-auto-generator will find the rootfs without `root=`
+    ///
+    /// Per the Discoverable Partitions Specification the root partition (or the LUKS
+    /// partition beneath it) must have the architecture specific root type, and be the
+    /// first such partition on the same disk as the ESP.
+    pub fn is_discoverable_root(&self, root: &BlockDevice<'_>, esp: impl AsRef<Path>) -> bool {
+        let Some(root_type) = dps_root_type() else {
+            return false;
+        };
+
+        // Only plain partitions, optionally encrypted, are discoverable
+        let layered = match root.children.as_slice() {
+            [] => false,
+            [child] if child.children.is_empty() => true,
+            _ => return false,
+        };
+        if layered && !matches!(root.kind, Some(superblock::Kind::LUKS1 | superblock::Kind::LUKS2)) {
+            return false;
+        }
+        if !root.btrfs_devices.is_empty()
+            || root.zfs_dataset.is_some()
+            || root.children.iter().any(|c| c.lvm_volume.is_some() || c.raid.is_some())
+        {
+            return false;
+        }
+
+        let partition = Path::new(&root.path);
+        let Some(disk) = self.get_device_parent(partition) else {
+            return false;
+        };
+        if self.get_device_parent(esp.as_ref()).as_ref() != Some(&disk) {
+            return false;
+        }
+        let Some(number) = self.get_partition_number(partition) else {
+            return false;
+        };
+
+        let Ok(fi) = fs::File::open(&disk) else {
+            return false;
+        };
+        let Ok(table) = gpt::GptConfig::new()
+            .writable(false)
+            .initialized(true)
+            .open_from_device(Box::new(fi))
+        else {
+            return false;
+        };
+        table
+            .partitions()
+            .iter()
+            .find(|(_, p)| p.part_type_guid.guid.eq_ignore_ascii_case(root_type))
+            .is_some_and(|(n, _)| *n == number)
+    }
+}
+
+/// Linux root (RISC-V 64-bit), not (yet) provided by [`gpt::partition_types`]
+const LINUX_ROOT_RISCV_64: gpt::partition_types::Type = gpt::partition_types::Type {
+    guid: "72EC70A6-CF74-40E6-BD49-4BDA08E8F224",
+    os: gpt::partition_types::OperatingSystem::Linux,
+};
+
+/// Root partition type for the running architecture, per the Discoverable Partitions Specification
+fn dps_root_type() -> Option<&'static str> {
+    if cfg!(target_arch = "x86_64") {
+        Some(gpt::partition_types::LINUX_ROOT_X64.guid)
+    } else if cfg!(target_arch = "aarch64") {
+        Some(gpt::partition_types::LINUX_ROOT_ARM_64.guid)
+    } else if cfg!(target_arch = "x86") {
+        Some(gpt::partition_types::LINUX_ROOT_X86.guid)
+    } else if cfg!(target_arch = "arm") {
+        Some(gpt::partition_types::LINUX_ROOT_ARM_32.guid)
+    } else if cfg!(target_arch = "riscv64") {
+        Some(LINUX_ROOT_RISCV_64.guid)
+    } else {
+        None
+    }
+}
+
+/// Split a colon-joined device spec (i.e. `/dev/sda1:/dev/sdb1`) into its members
+///
+/// Returns an empty list for ordinary devices, including those with a colon in their path
+fn split_device_spec(device: &Path) -> Vec<PathBuf> {
+    if device.exists() {
+        return vec![];
+    }
+    let spec = device.to_string_lossy();
+    let members = spec.split(':').map(PathBuf::from).collect::<Vec<_>>();
+    if members.len() > 1 && members.iter().all(|m| m.exists()) {
+        members
+    } else {
+        vec![]
+    }
+}
+
+/// Split a device-mapper LVM name into the VG and LV names.
+/// Hyphens within either name are escaped by doubling them, i.e. `my--vg-root`
+fn split_lvm_name(name: &str) -> Option<(String, String)> {
+    let mut vg = String::new();
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('-', Some('-')) => {
+                chars.next();
+                vg.push('-');
+            }
+            ('-', _) => {
+                let lv = chars.collect::<String>().replace("--", "-");
+                return (!vg.is_empty() && !lv.is_empty()).then_some((vg, lv));
+            }
+            (c, _) => vg.push(c),
+        }
+    }
+    None
 }
@@ -0,0 +1,143 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Processing of `/proc/swaps`
+
+use std::{
+    fs, io,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+use super::mounts;
+
+/// Type of the swap area
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Swap partition (or any block device)
+    Partition,
+
+    /// Swap file living on some filesystem
+    File,
+}
+
+/// An active swap area
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Swap {
+    /// Path of the device or file
+    pub path: PathBuf,
+
+    /// Partition or file
+    pub kind: Kind,
+
+    /// Size in KiB
+    pub size: u64,
+
+    /// Used space in KiB
+    pub used: u64,
+
+    /// Kernel priority, higher is used first
+    pub priority: i32,
+}
+
+impl Swap {
+    /// Returns true if this swap area is volatile (i.e. zram) and unusable for hibernation
+    pub fn is_volatile(&self) -> bool {
+        self.path
+            .file_name()
+            .is_some_and(|f| f.to_string_lossy().starts_with("zram"))
+    }
+}
+
+/// Parse the contents of `/proc/swaps`, skipping the header
+pub fn parse(data: &str) -> Vec<Swap> {
+    data.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut splits = line.split_ascii_whitespace();
+            let path = PathBuf::from(mounts::unescape(splits.next()?));
+            let kind = match splits.next()? {
+                "file" => Kind::File,
+                _ => Kind::Partition,
+            };
+            Some(Swap {
+                path,
+                kind,
+                size: splits.next()?.parse().ok()?,
+                used: splits.next()?.parse().ok()?,
+                priority: splits.next()?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Load active swaps from the given path (i.e. `/proc/swaps`)
+///
+/// A missing file (no swap support) is treated as having no swaps
+pub fn new_from_path(path: impl AsRef<Path>) -> Result<Vec<Swap>, io::Error> {
+    match fs::read_to_string(path) {
+        Ok(data) => Ok(parse(&data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e),
+    }
+}
+
+/// struct fiemap, with room for a single extent
+#[repr(C)]
+#[derive(Default)]
+struct Fiemap {
+    start: u64,
+    length: u64,
+    flags: u32,
+    mapped_extents: u32,
+    extent_count: u32,
+    reserved: u32,
+    extent: FiemapExtent,
+}
+
+/// struct fiemap_extent
+#[repr(C)]
+#[derive(Default)]
+struct FiemapExtent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    reserved64: [u64; 2],
+    flags: u32,
+    reserved: [u32; 3],
+}
+
+const FIEMAP_FLAG_SYNC: u32 = 0x1;
+
+// FS_IOC_FIEMAP, sized by the header only
+nix::ioctl_readwrite_bad!(
+    fs_ioc_fiemap,
+    nix::request_code_readwrite!(
+        b'f',
+        11,
+        std::mem::size_of::<Fiemap>() - std::mem::size_of::<FiemapExtent>()
+    ),
+    Fiemap
+);
+
+/// Compute the `resume_offset=` for a swap file: the physical offset of its
+/// first extent in units of the page size.
+///
+/// Note: This is incorrect for btrfs, which reports logical addresses.
+pub fn swapfile_offset(path: impl AsRef<Path>) -> Result<u64, super::Error> {
+    let path = path.as_ref();
+    let file = fs::File::open(path)?;
+    let mut map = Fiemap {
+        length: u64::MAX,
+        flags: FIEMAP_FLAG_SYNC,
+        extent_count: 1,
+        ..Default::default()
+    };
+    unsafe { fs_ioc_fiemap(file.as_raw_fd(), &mut map) }?;
+    if map.mapped_extents == 0 {
+        return Err(super::Error::InvalidDevice(path.to_path_buf()));
+    }
+    let page_size = nix::unistd::sysconf(nix::unistd::SysconfVar::PAGE_SIZE)?.unwrap_or(4096) as u64;
+    Ok(map.extent.physical / page_size)
+}
@@ -0,0 +1,38 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! bcachefs root spanning two devices, mounted as `dev1:dev2`
+
+use std::{env, path::PathBuf};
+
+use topology::disk::Builder;
+
+#[test]
+fn topology_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/bcachefs_multi/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/bcachefs_multi/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/bcachefs_multi/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    let root_device = topo.get_device_from_mountpoint("/").expect("Cannot find root device");
+    assert_eq!(
+        root_device,
+        PathBuf::from("tests/bcachefs_multi/dev/sda1:tests/bcachefs_multi/dev/sdb1")
+    );
+
+    let member = topo
+        .get_device_superblock("tests/bcachefs_multi/dev/sdb1")
+        .expect("need member superblock");
+    assert_eq!(member.kind(), superblock::Kind::Bcachefs);
+    assert_eq!(member.uuid().unwrap(), "d4e8b2c6-1a3f-4e7d-9c5b-8f2a6e0d4c1b");
+
+    let block = topo.get_rootfs_device("/").expect("Failed to determine block device");
+    assert_eq!(block.children.len(), 1);
+    assert_eq!(
+        block.cmd_line(),
+        "root=UUID=d4e8b2c6-1a3f-4e7d-9c5b-8f2a6e0d4c1b rootfstype=bcachefs"
+    );
+}
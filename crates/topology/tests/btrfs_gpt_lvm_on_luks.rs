@@ -22,15 +22,49 @@ fn topology_test() {
         root_device,
         PathBuf::from("tests/btrfs_gpt_lvm_on_luks/dev/mapper/BogusInstall-root")
     );
-    let sb = topo.get_device_superblock(root_device).expect("need uuid");
+    let sb = topo.get_device_superblock(&root_device).expect("need uuid");
     assert_eq!(sb.uuid().unwrap(), "2a78a4da-f110-4441-8839-dbd97ab87cda");
     assert_eq!(sb.kind(), superblock::Kind::Btrfs);
+    let pv = topo
+        .get_device_superblock(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/btrfs_gpt_lvm_on_luks/dev/dm-0"
+        ))
+        .expect("need pv label");
+    assert_eq!(pv.kind(), superblock::Kind::LVM2);
+    assert_eq!(pv.uuid().unwrap(), "bPOhJ4-kLW5-pWYb-2ePD-Kd3c-HXVz-Xz6Y2G");
+    assert_eq!(topo.get_lvm_volume(&root_device).as_deref(), Some("BogusInstall/root"));
+    assert!(!topo.is_plain_crypt(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/btrfs_gpt_lvm_on_luks/dev/dm-0"
+    )));
+
     let block = topo.get_rootfs_device("/").expect("Failed to determine block device");
 
     let cmdline = block.cmd_line();
-    // PartUUID is the only one we want.
+    // Only the root LV needs activating
     assert_eq!(
         cmdline,
-        "rd.luks.uuid=b6b31f26-39f4-48f7-bed5-6faaff96cca4 root=UUID=2a78a4da-f110-4441-8839-dbd97ab87cda rootfsflags=subvol=/"
+        "rd.luks.uuid=b6b31f26-39f4-48f7-bed5-6faaff96cca4 root=UUID=2a78a4da-f110-4441-8839-dbd97ab87cda rootflags=subvol=/ rd.lvm.lv=BogusInstall/root"
+    );
+}
+
+#[test]
+fn resume_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/btrfs_gpt_lvm_on_luks/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/btrfs_gpt_lvm_on_luks/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/btrfs_gpt_lvm_on_luks/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    // Swap LV listed by its `/dev/mapper` name
+    let block = topo
+        .get_resume_device()
+        .expect("Failed to determine resume device")
+        .expect("Missing resume device");
+    assert_eq!(
+        block.cmd_line(),
+        "rd.luks.uuid=b6b31f26-39f4-48f7-bed5-6faaff96cca4 resume=UUID=9d4b7c1e-2f3a-4b5c-8d6e-7f8091a2b3c4 rd.lvm.lv=BogusInstall/swap"
     );
 }
@@ -0,0 +1,64 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Multi-device btrfs root, mounted by `subvolid=` only, with subvolumes from mountinfo
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use topology::disk::{mounts::MountOption, Builder};
+
+#[test]
+fn topology_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/btrfs_raid1/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/btrfs_raid1/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/btrfs_raid1/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    let root_device = topo.get_device_from_mountpoint("/").expect("Cannot find root device");
+    assert_eq!(root_device, PathBuf::from("tests/btrfs_raid1/dev/sda1"));
+    let sb = topo.get_device_superblock(&root_device).expect("need uuid");
+    assert_eq!(sb.kind(), superblock::Kind::Btrfs);
+    assert_eq!(sb.label().unwrap(), "blsforme raid1");
+
+    let devices = topo.get_btrfs_devices("e3b1a5c7-2f4d-4e8a-9b6c-1d0f2a3b4c5d");
+    assert_eq!(devices.len(), 2);
+    assert_eq!(topo.get_mount_root("/").as_deref(), Some("/@"));
+
+    let home = topo
+        .mountinfo
+        .iter()
+        .find(|m| m.mountpoint == Path::new("/home dir"))
+        .expect("Missing escaped mountpoint");
+    assert_eq!((home.mount_id, home.parent_id), (23, 22));
+    assert_eq!((home.major, home.minor), (8, 1));
+    assert_eq!(home.root, Path::new("/@home"));
+    assert_eq!(home.optional_fields, vec!["shared:2", "master:1"]);
+    assert_eq!(home.filesystem, "btrfs");
+    assert!(home
+        .super_options()
+        .any(|o| matches!(o, MountOption::Option("subvolid", "257"))));
+
+    // Looked up by device the last mount wins, unescaped just the same
+    assert_eq!(
+        topo.get_mountpoint_from_device(&root_device),
+        Some(PathBuf::from("/home dir"))
+    );
+
+    let block = topo.get_rootfs_device("/").expect("Failed to determine block device");
+    assert_eq!(
+        block.cmd_line(),
+        concat!(
+            "root=UUID=e3b1a5c7-2f4d-4e8a-9b6c-1d0f2a3b4c5d rootflags=subvol=/@,device=",
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/btrfs_raid1/dev/sda1,device=",
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/btrfs_raid1/dev/sdb1"
+        )
+    );
+}
@@ -0,0 +1,24 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Root partition carrying both a btrfs and a stale ext4 signature
+
+use topology::disk::{Builder, Error};
+
+#[test]
+fn topology_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_ambiguous/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_ambiguous/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_ambiguous/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    // Guessing either would risk an unbootable `root=`, so refuse outright
+    let result = topo.get_rootfs_device("/");
+    assert!(matches!(
+        result,
+        Err(Error::Superblock(superblock::Error::AmbiguousSuperblock(_)))
+    ));
+}
@@ -28,3 +28,25 @@ fn topology_test() {
     // PartUUID is the only one we want.
     assert_eq!(cmdline, "root=PARTUUID=6ca59a0c-e8c9-4ec4-b331-351d120fbb32");
 }
+
+#[test]
+fn resume_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_gpt/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_gpt/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_gpt/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    let swaps = topo.get_swaps().expect("Cannot read swaps");
+    assert_eq!(swaps.len(), 2);
+    assert!(swaps[0].is_volatile());
+
+    // zram is skipped despite the higher priority
+    let block = topo
+        .get_resume_device()
+        .expect("Failed to determine resume device")
+        .expect("Missing resume device");
+    assert_eq!(block.kind, Some(superblock::Kind::Swap));
+    assert_eq!(block.cmd_line(), "resume=UUID=0b9e3b41-43c5-4d8e-8a4c-6b1f0c0e2d7a");
+}
@@ -0,0 +1,33 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! ext4 root on a LUKS2 mapping whose header is detached from the backing device
+
+use std::{env, path::PathBuf};
+
+use topology::disk::Builder;
+
+#[test]
+fn topology_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_luks_detached/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_luks_detached/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_luks_detached/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    let root_device = topo.get_device_from_mountpoint("/").expect("Cannot find root device");
+    assert_eq!(root_device, PathBuf::from("tests/ext4_luks_detached/dev/dm-0"));
+    assert!(!topo.is_plain_crypt(&root_device));
+    assert_eq!(
+        topo.get_detached_luks(&root_device).as_deref(),
+        Some("7f3e2d1c-0b9a-48e7-a6f5-e4d3c2b1a098")
+    );
+
+    let block = topo.get_rootfs_device("/").expect("Failed to determine block device");
+    assert_eq!(
+        block.cmd_line(),
+        "root=UUID=0e1d9a7c-5b3f-4e2a-8c6d-1f0b2a3c4d5e rd.luks.uuid=7f3e2d1c-0b9a-48e7-a6f5-e4d3c2b1a098"
+    );
+}
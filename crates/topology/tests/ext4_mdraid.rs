@@ -0,0 +1,62 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Ensure proper detection of ext4 roots on mdraid, with a RAID1 ESP
+
+use std::{env, path::PathBuf};
+
+use topology::disk::Builder;
+
+#[test]
+fn topology_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_mdraid/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_mdraid/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_mdraid/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    let root_device = topo.get_device_from_mountpoint("/").expect("Cannot find root device");
+    assert_eq!(root_device, PathBuf::from("tests/ext4_mdraid/dev/md0"));
+
+    let raid = topo.get_raid(&root_device).expect("Missing RAID details");
+    assert_eq!(raid.level, "raid1");
+    assert_eq!(raid.members.len(), 2);
+    assert_eq!(raid.uuid.as_deref(), Some("9d2e7f4a:61c34b8e:8f0a2c5d:7e9b1a36"));
+
+    let block = topo.get_rootfs_device("/").expect("Failed to determine block device");
+    assert_eq!(
+        block.cmd_line(),
+        "root=UUID=4c8b1e2a-7d3f-4a9c-b5e6-0f1a2b3c4d5e rd.md.uuid=9d2e7f4a:61c34b8e:8f0a2c5d:7e9b1a36"
+    );
+}
+
+#[test]
+fn esp_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_mdraid/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_mdraid/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_mdraid/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    // Firmware sees the member partition as FAT, metadata 1.0 lives at the end
+    let member = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/ext4_mdraid/dev/sda2");
+    let sb = topo.get_device_superblock(member).expect("need md superblock");
+    assert_eq!(sb.kind(), superblock::Kind::MdRaid);
+
+    let array = topo.get_raid_holder(member).expect("Missing RAID holder");
+    assert!(array.ends_with("md1"));
+    let raid = topo.get_raid(&array).expect("Missing RAID details");
+    assert_eq!(raid.metadata, "1.0");
+    assert_eq!(raid.uuid.as_deref(), Some("3f5d1c2a:b8e94c07:a1d26e4f:90b7c358"));
+
+    let block = topo
+        .get_rootfs_device("/mnt")
+        .expect("Failed to determine block device");
+    assert_eq!(
+        block.cmd_line(),
+        "root=UUID=7A3C-19E5 rd.md.uuid=3f5d1c2a:b8e94c07:a1d26e4f:90b7c358"
+    );
+}
@@ -0,0 +1,51 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! Separate `/usr` partition protected by dm-verity, laid out per the DPS
+
+use std::{env, path::PathBuf};
+
+use topology::disk::Builder;
+
+#[test]
+fn topology_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/usr_verity/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/usr_verity/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/usr_verity/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    let root = topo.get_rootfs_device("/").expect("Failed to determine block device");
+    assert_eq!(root.cmd_line(), "root=PARTUUID=9c2f4a6e-8b1d-4e3f-a5c7-0e2d4f6b8a1c");
+
+    // First root partition on the ESP's disk, so `root=` may be omitted
+    // (the fixture uses the x86-64 root type)
+    let esp = "tests/usr_verity/dev/sda4";
+    assert_eq!(topo.is_discoverable_root(&root, esp), cfg!(target_arch = "x86_64"));
+    assert!(!topo.is_discoverable_root(&root, "tests/usr_verity/dev/dm-0"));
+
+    // Second root partition (A/B) isn't picked by systemd-gpt-auto-generator
+    let root_b = topo
+        .get_rootfs_device("/mnt")
+        .expect("Failed to determine block device");
+    assert!(!topo.is_discoverable_root(&root_b, esp));
+    assert!(topo.is_mounted_read_only("/mnt"));
+    assert!(!topo.is_mounted_read_only("/"));
+
+    let usr_device = topo.get_device_from_mountpoint("/usr").expect("Cannot find usr device");
+    assert_eq!(usr_device, PathBuf::from("tests/usr_verity/dev/dm-0"));
+    let verity = topo.get_verity(&usr_device).expect("Missing verity details");
+    assert!(verity.hash.ends_with("sda3"));
+    assert!(verity.data.ends_with("sda2"));
+
+    let usr = topo
+        .get_usr_device("/")
+        .expect("Failed to determine usr device")
+        .expect("usr should be a separate mount");
+    assert_eq!(
+        usr.cmd_line(),
+        "usrhash=b7c1d9e35f2a4b8c9d6e1a3f5c7e9b2d4e6a8c0f2b4d4f6a8c1e3d5f7a9b1c3e mount.usr=/dev/mapper/usr mount.usrfstype=ext4"
+    );
+}
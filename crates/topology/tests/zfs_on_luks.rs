@@ -0,0 +1,41 @@
+// SPDX-FileCopyrightText: Copyright © 2025 Serpent OS Developers
+//
+// SPDX-License-Identifier: MPL-2.0
+
+//! ZFS root dataset in a pool atop LUKS
+
+use std::{env, path::PathBuf};
+
+use topology::disk::Builder;
+
+#[test]
+fn topology_test() {
+    let topo = Builder::default()
+        .with_devfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/zfs_on_luks/dev"))
+        .with_sysfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/zfs_on_luks/sys"))
+        .with_procfs(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/zfs_on_luks/proc"))
+        .build()
+        .expect("Failed to create Probe");
+
+    let root_device = topo.get_device_from_mountpoint("/").expect("Cannot find root device");
+    assert_eq!(root_device, PathBuf::from("rpool/ROOT/serpent"));
+    assert_eq!(topo.get_mount_filesystem("/").as_deref(), Some("zfs"));
+
+    let members = topo.get_zfs_members("rpool");
+    assert_eq!(
+        members,
+        vec![PathBuf::from(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/zfs_on_luks/dev/dm-0"
+        ))]
+    );
+    let sb = topo.get_device_superblock(&members[0]).expect("need vdev label");
+    assert_eq!(sb.kind(), superblock::Kind::ZFS);
+    assert_eq!(sb.uuid().unwrap(), "11478261563213004345");
+
+    let block = topo.get_rootfs_device("/").expect("Failed to determine block device");
+    assert_eq!(
+        block.cmd_line(),
+        "root=ZFS=rpool/ROOT/serpent rd.luks.uuid=6c1f8d2e-3a4b-4c5d-9e6f-7a8b9c0d1e2f"
+    );
+}